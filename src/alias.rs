@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::redact::Redactor;
+
+/// Friendlier ids written in place of raw Homie device, node and property ids, keyed by
+/// the raw id. Aliased ids aren't redacted.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Aliases {
+    pub devices: HashMap<String, String>,
+    pub nodes: HashMap<String, String>,
+    pub properties: HashMap<String, String>,
+    /// also tag points with each aliased id as it was, e.g. `original_device_id_tag`
+    pub keep_original: bool,
+}
+
+impl Aliases {
+    /// The ids a value of `device_id/node_id/property_id` is written with, and the tags
+    /// keeping the originals of those that were aliased, if asked to.
+    pub fn apply<'a>(
+        &self,
+        redactor: &Redactor,
+        device_id: &'a str,
+        node_id: &'a str,
+        property_id: &'a str
+    ) -> ([Cow<'a, str>; 3], Vec<(String, String)>) {
+        let mut originals = Vec::new();
+        let mut resolve = |aliases: &HashMap<String, String>, id: &'a str, tag: &str| {
+            match aliases.get(id) {
+                Some(alias) => {
+                    if self.keep_original {
+                        originals.push((tag.to_string(), redactor.apply(id).into_owned()));
+                    }
+                    Cow::Owned(alias.clone())
+                }
+                None => redactor.apply(id),
+            }
+        };
+        let ids = [
+            resolve(&self.devices, device_id, "original_device_id_tag"),
+            resolve(&self.nodes, node_id, "original_node_id_tag"),
+            resolve(&self.properties, property_id, "original_property_id_tag"),
+        ];
+        (ids, originals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactRule;
+
+    fn redactor() -> Redactor {
+        let rule = RedactRule { pattern: "[0-9a-f]{12}".to_string(), replacement: "MAC".to_string() };
+        Redactor::new(&[rule]).unwrap()
+    }
+
+    fn aliases(keep_original: bool) -> Aliases {
+        Aliases {
+            devices: HashMap::from([("esp-a4cf12f3b8e0".to_string(), "hallway".to_string())]),
+            nodes: HashMap::new(),
+            properties: HashMap::from([("temp".to_string(), "temperature".to_string())]),
+            keep_original,
+        }
+    }
+
+    #[test]
+    fn aliased_ids_replace_raw_ones_and_the_rest_are_redacted() {
+        let (ids, originals) = aliases(false).apply(
+            &redactor(),
+            "esp-a4cf12f3b8e0",
+            "sensor-0011223344ff",
+            "temp"
+        );
+        assert_eq!(ids, ["hallway", "sensor-MAC", "temperature"]);
+        assert!(originals.is_empty());
+    }
+
+    #[test]
+    fn originals_are_kept_redacted() {
+        let (_, originals) = aliases(true).apply(&redactor(), "esp-a4cf12f3b8e0", "sensor", "temp");
+        assert_eq!(originals, vec![
+            ("original_device_id_tag".to_string(), "esp-MAC".to_string()),
+            ("original_property_id_tag".to_string(), "temp".to_string())
+        ]);
+    }
+}
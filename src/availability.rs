@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use homie_controller::State;
+use tokio::time::Instant;
+
+/// Whether a device in `state` counts as available; `None` while its state is unknown.
+fn is_online(state: State) -> Option<bool> {
+    match state {
+        State::Unknown => None,
+        State::Disconnected | State::Lost => Some(false),
+        State::Init | State::Ready | State::Sleeping | State::Alert => Some(true),
+    }
+}
+
+/// Per-device online/offline history from `$state` transitions, giving the share of the
+/// last `window` each device was online. Time before a device's state was first seen, e.g.
+/// before the bridge started, counts neither way. A device is only taken as lost once it
+/// has been `lost` for `lost_grace`, and then from when it went lost; a blip during a
+/// reconnect leaves no trace.
+#[derive(Debug)]
+pub struct Availability {
+    window: Duration,
+    lost_grace: Duration,
+    // transitions per device in time order, the first one at or before the window's start
+    transitions: HashMap<String, Vec<(Instant, Option<bool>)>>,
+    // when each device went lost, while within the grace period
+    pending_lost: HashMap<String, Instant>,
+}
+
+impl Availability {
+    pub fn new(window: Duration, lost_grace: Duration) -> Availability {
+        Availability {
+            window,
+            lost_grace,
+            transitions: HashMap::new(),
+            pending_lost: HashMap::new(),
+        }
+    }
+
+    /// Record the device's state; repeats of the current state are ignored.
+    pub fn update(&mut self, device_id: &str, state: State, now: Instant) {
+        if state == State::Lost && !self.lost_grace.is_zero() {
+            self.pending_lost.entry(device_id.to_string()).or_insert(now);
+            return;
+        }
+        if self.pending_lost.remove(device_id).is_some() {
+            debug!("{} back within the lost grace period", device_id);
+        }
+        self.push(device_id, is_online(state), now);
+    }
+
+    fn push(&mut self, device_id: &str, online: Option<bool>, at: Instant) {
+        let transitions = self.transitions.entry(device_id.to_string()).or_default();
+        if transitions.last().map(|(_, last)| *last) != Some(online) {
+            transitions.push((at, online));
+        }
+    }
+
+    /// Record devices lost for longer than the grace period as lost since they went.
+    fn confirm_lost(&mut self, now: Instant) {
+        let grace = self.lost_grace;
+        let lost: Vec<(String, Instant)> = self.pending_lost
+            .iter()
+            .filter(|(_, since)| now.saturating_duration_since(**since) >= grace)
+            .map(|(device_id, since)| (device_id.clone(), *since))
+            .collect();
+        for (device_id, since) in lost {
+            info!("{} lost for over {:?}", device_id, grace);
+            self.pending_lost.remove(&device_id);
+            self.push(&device_id, Some(false), since);
+        }
+    }
+
+    /// The ratio of online to known time over the window ending at `now`, per device whose
+    /// state was known for any of it. Transitions no longer needed are dropped.
+    pub fn ratios(&mut self, now: Instant) -> Vec<(String, f64)> {
+        self.confirm_lost(now);
+        let start = now.checked_sub(self.window).unwrap_or(now);
+        let mut ratios = Vec::new();
+        for (device_id, transitions) in &mut self.transitions {
+            // keep the last transition before the window, it gives the state at its start
+            let before = transitions.iter().take_while(|(at, _)| *at <= start).count();
+            transitions.drain(..before.saturating_sub(1));
+
+            let mut known = Duration::ZERO;
+            let mut online = Duration::ZERO;
+            for (i, (at, state)) in transitions.iter().enumerate() {
+                let until = transitions.get(i + 1).map_or(now, |(next, _)| *next);
+                let span = until.saturating_duration_since((*at).max(start));
+                match state {
+                    Some(true) => {
+                        known += span;
+                        online += span;
+                    }
+                    Some(false) => {
+                        known += span;
+                    }
+                    None => {}
+                }
+            }
+            if !known.is_zero() {
+                ratios.push((device_id.clone(), online.as_secs_f64() / known.as_secs_f64()));
+            }
+        }
+        ratios
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(100);
+    const GRACE: Duration = Duration::from_secs(10);
+
+    fn at(start: Instant, seconds: u64) -> Instant {
+        start + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn ratio_of_online_time_in_the_window() {
+        let start = Instant::now();
+        let mut availability = Availability::new(WINDOW, Duration::ZERO);
+        availability.update("thermostat", State::Ready, start);
+        availability.update("thermostat", State::Disconnected, at(start, 60));
+        assert_eq!(availability.ratios(at(start, 100)), [("thermostat".to_string(), 0.6)]);
+        // the window slides past the online time
+        assert_eq!(availability.ratios(at(start, 160)), [("thermostat".to_string(), 0.0)]);
+    }
+
+    #[test]
+    fn unknown_time_counts_neither_way() {
+        let start = Instant::now();
+        let mut availability = Availability::new(WINDOW, Duration::ZERO);
+        availability.update("thermostat", State::Unknown, start);
+        assert!(availability.ratios(at(start, 50)).is_empty());
+        availability.update("thermostat", State::Ready, at(start, 50));
+        assert_eq!(availability.ratios(at(start, 100)), [("thermostat".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn lost_blip_within_grace_leaves_no_trace() {
+        let start = Instant::now();
+        let mut availability = Availability::new(WINDOW, GRACE);
+        availability.update("thermostat", State::Ready, start);
+        availability.update("thermostat", State::Lost, at(start, 50));
+        availability.update("thermostat", State::Ready, at(start, 55));
+        assert_eq!(availability.ratios(at(start, 100)), [("thermostat".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn lost_past_grace_counts_from_when_it_went() {
+        let start = Instant::now();
+        let mut availability = Availability::new(WINDOW, GRACE);
+        availability.update("thermostat", State::Ready, start);
+        availability.update("thermostat", State::Lost, at(start, 50));
+        assert_eq!(availability.ratios(at(start, 100)), [("thermostat".to_string(), 0.5)]);
+    }
+}
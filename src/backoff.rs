@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Exponential backoff with a cap and "full jitter": the delay before retry `attempt` is
+/// drawn uniformly from zero up to `min(cap, base * 2^attempt)`, which spreads retries from
+/// many clients apart instead of having them hit a recovering server in lock step.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    base: Duration,
+    cap: Duration,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, cap: Duration) -> Backoff {
+        Backoff { base, cap: cap.max(base) }
+    }
+
+    /// Longest delay before retry `attempt` (counting from 0), before jitter.
+    pub fn ceiling(&self, attempt: u32) -> Duration {
+        let factor = (2u32).checked_pow(attempt).unwrap_or(u32::MAX);
+        self.base.checked_mul(factor).unwrap_or(self.cap).min(self.cap)
+    }
+
+    /// Jittered delay before retry `attempt`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        rand::thread_rng().gen_range(Duration::ZERO..=self.ceiling(attempt))
+    }
+}
+
+/// Random delay of up to `max` before connecting, so bridges restarted together don't all
+/// reach the broker and the sink at once.
+pub fn startup_jitter(max: Duration) -> Duration {
+    rand::thread_rng().gen_range(Duration::ZERO..=max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ceiling_doubles_up_to_the_cap() {
+        let backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+        assert_eq!(backoff.ceiling(0), Duration::from_millis(500));
+        assert_eq!(backoff.ceiling(1), Duration::from_secs(1));
+        assert_eq!(backoff.ceiling(2), Duration::from_secs(2));
+        assert_eq!(backoff.ceiling(3), Duration::from_secs(3));
+        // no overflow however many attempts
+        assert_eq!(backoff.ceiling(200), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn cap_is_at_least_the_base() {
+        let backoff = Backoff::new(Duration::from_secs(2), Duration::from_secs(1));
+        assert_eq!(backoff.ceiling(5), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn delay_is_jittered_below_the_ceiling() {
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        for attempt in 0..10 {
+            assert!(backoff.delay(attempt) <= backoff.ceiling(attempt));
+        }
+    }
+
+    #[test]
+    fn startup_jitter_stays_within_the_bound() {
+        let max = Duration::from_millis(250);
+        for _ in 0..100 {
+            assert!(startup_jitter(max) <= max);
+        }
+        assert_eq!(startup_jitter(Duration::ZERO), Duration::ZERO);
+    }
+}
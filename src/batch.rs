@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::point::HomiePoint;
+
+/// A sink's flush policy: points are held until `size` of them are pending or `interval` has
+/// passed since the last flush. With neither set every batch is flushed as it comes.
+#[derive(Debug)]
+pub struct Batcher {
+    size: usize,
+    interval: Duration,
+    pending: Vec<HomiePoint>,
+    last_flush: Instant,
+}
+
+impl Batcher {
+    pub fn new(size: usize, interval: Duration, now: Instant) -> Batcher {
+        Batcher { size, interval, pending: Vec::new(), last_flush: now }
+    }
+
+    pub fn push(&mut self, points: Vec<HomiePoint>) {
+        self.pending.extend(points);
+    }
+
+    /// The pending points if the policy says they are due.
+    pub fn take_due(&mut self, now: Instant) -> Option<Vec<HomiePoint>> {
+        let unbatched = self.size == 0 && self.interval.is_zero();
+        let full = self.size > 0 && self.pending.len() >= self.size;
+        let expired = !self.interval.is_zero() && now.duration_since(self.last_flush) >= self.interval;
+        if self.pending.is_empty() || !(unbatched || full || expired) {
+            return None;
+        }
+        self.last_flush = now;
+        Some(self.take())
+    }
+
+    /// All pending points, e.g. on shutdown.
+    pub fn take(&mut self) -> Vec<HomiePoint> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(count: usize) -> Vec<HomiePoint> {
+        (0..count).map(|i| HomiePoint::new(i as f64, "thermostat", "zone", "temperature", 1_000)).collect()
+    }
+
+    #[test]
+    fn unbatched_flushes_every_push() {
+        let now = Instant::now();
+        let mut batcher = Batcher::new(0, Duration::ZERO, now);
+        assert!(batcher.take_due(now).is_none());
+        batcher.push(points(1));
+        assert_eq!(batcher.take_due(now).map(|points| points.len()), Some(1));
+    }
+
+    #[test]
+    fn flushes_once_full() {
+        let now = Instant::now();
+        let mut batcher = Batcher::new(3, Duration::from_secs(60), now);
+        batcher.push(points(2));
+        assert!(batcher.take_due(now).is_none());
+        batcher.push(points(2));
+        assert_eq!(batcher.take_due(now).map(|points| points.len()), Some(4));
+    }
+
+    #[test]
+    fn flushes_once_the_interval_has_passed() {
+        let start = Instant::now();
+        let mut batcher = Batcher::new(100, Duration::from_secs(10), start);
+        batcher.push(points(1));
+        assert!(batcher.take_due(start + Duration::from_secs(9)).is_none());
+        assert_eq!(batcher.take_due(start + Duration::from_secs(10)).map(|points| points.len()), Some(1));
+
+        batcher.push(points(1));
+        assert!(batcher.take_due(start + Duration::from_secs(15)).is_none());
+        assert_eq!(batcher.take().len(), 1);
+    }
+}
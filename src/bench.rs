@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::config::Config;
+use crate::control::Control;
+use crate::pipeline::{ self, Pipeline };
+use crate::profile::Latencies;
+
+// values a bench property is given besides numbers and booleans, so mode tables and the
+// fallback for unconvertible values are exercised
+const MODE_VALUES: &[&str] = &[
+    "cooling",
+    "heating",
+    "standby",
+    "auto",
+    "heat",
+    "continuous",
+    "economy",
+    "error",
+];
+
+/// What a conversion benchmark measured.
+#[derive(Debug)]
+pub struct BenchReport {
+    pub conversions: u64,
+    pub points: u64,
+    pub elapsed: Duration,
+    pub latencies: Latencies,
+}
+
+impl BenchReport {
+    pub fn rate(&self) -> f64 {
+        (self.conversions as f64) / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Run `iterations` random values through a pipeline built from `config`, timing each. The
+/// properties are those the config has per-property settings for, plus one per built-in
+/// mode table and a plain `temperature`, so the config's own mappings are what gets measured.
+pub fn bench_convert(config: Config, options: pipeline::Options, iterations: u64) -> BenchReport {
+    let properties = properties(&config);
+    let mut pipeline = Pipeline::new(config, options, Arc::new(Control::default()));
+    let mut rng = rand::thread_rng();
+    let mut latencies = Latencies::default();
+    let mut points = 0;
+
+    let started = Instant::now();
+    for n in 0..iterations {
+        let (device_id, node_id, property_id) = properties.choose(&mut rng).unwrap();
+        let value = match rng.gen_range(0..4) {
+            0 => rng.gen_bool(0.5).to_string(),
+            1 => MODE_VALUES.choose(&mut rng).unwrap().to_string(),
+            _ => format!("{:.2}", rng.gen_range(-50.0..150.0)),
+        };
+        let converted = Instant::now();
+        points += pipeline.process(device_id, node_id, property_id, value, n as i64).len() as u64;
+        latencies.add(converted.elapsed());
+    }
+
+    BenchReport { conversions: iterations, points, elapsed: started.elapsed(), latencies }
+}
+
+/// `device/node/property` ids for every per-property key in the config, filling in a bench
+/// device and node for keys that leave them out.
+fn properties(config: &Config) -> Vec<(String, String, String)> {
+    let keys = config.smoothing
+        .keys()
+        .chain(config.integer_fields.keys())
+        .chain(config.sampling.keys())
+        .chain(config.rate.keys())
+        .chain(config.boolean.keys())
+        .chain(config.extract.keys())
+        .chain(config.tables.keys())
+        .chain(config.when.keys())
+        .chain(config.metric_types.keys())
+        .chain(config.invalid.keys())
+        .map(String::as_str)
+        .chain(["current_mode", "target_mode", "target_fan_mode", "humidifier_mode", "temperature"]);
+
+    let mut properties: Vec<(String, String, String)> = keys
+        .map(|key| {
+            let mut ids = key.rsplitn(3, '/');
+            let property_id = ids.next().unwrap_or_default().to_string();
+            let node_id = ids.next().unwrap_or("bench-node").to_string();
+            let device_id = ids.next().unwrap_or("bench-device").to_string();
+            (device_id, node_id, property_id)
+        })
+        .collect();
+    properties.sort();
+    properties.dedup();
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::EmptyValue;
+    use crate::rate::Rate;
+
+    fn options() -> pipeline::Options {
+        pipeline::Options {
+            trim: true,
+            dump_raw: false,
+            series_cache_size: 16,
+            empty_value: EmptyValue::Zero,
+            emit_provenance: false,
+            emit_raw_string: false,
+            enum_string_tag: false,
+        }
+    }
+
+    #[test]
+    fn config_keys_are_benched_with_bench_ids_filled_in() {
+        let mut config = Config::default();
+        config.rate.insert("meter/main/energy".to_string(), Rate { scale: 1.0 });
+        config.rate.insert("power".to_string(), Rate { scale: 1.0 });
+
+        let properties = properties(&config);
+        let ids = |device: &str, node: &str, property: &str| {
+            (device.to_string(), node.to_string(), property.to_string())
+        };
+        assert!(properties.contains(&ids("meter", "main", "energy")));
+        assert!(properties.contains(&ids("bench-device", "bench-node", "power")));
+        assert!(properties.contains(&ids("bench-device", "bench-node", "temperature")));
+        assert_eq!(properties.len(), 7);
+    }
+
+    #[test]
+    fn every_iteration_is_a_conversion() {
+        let report = bench_convert(Config::default(), options(), 200);
+        assert_eq!(report.conversions, 200);
+        assert!(report.points > 0);
+        assert!(report.rate() > 0.0);
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Circuit breaker around the sink. After `threshold` writes in a row fail it opens, and
+/// points are handled as if the sink were paused rather than each retrying against a dead
+/// endpoint. Once `cooldown` has passed it half-opens: the next write is a single-point
+/// probe, closing the breaker if it lands and opening it again if not.
+#[derive(Debug)]
+pub struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    opened: Option<Instant>,
+}
+
+impl Breaker {
+    /// A breaker opening after `threshold` failures; 0 never opens.
+    pub fn new(threshold: u32, cooldown: Duration) -> Breaker {
+        Breaker { threshold, cooldown, failures: 0, opened: None }
+    }
+
+    /// Open and still cooling down: writes should fail fast.
+    pub fn is_open(&self, now: Instant) -> bool {
+        self.opened.is_some_and(|opened| now.duration_since(opened) < self.cooldown)
+    }
+
+    /// Cooled down: the next write should be a probe.
+    pub fn is_half_open(&self, now: Instant) -> bool {
+        self.opened.is_some() && !self.is_open(now)
+    }
+
+    pub fn record_success(&mut self) {
+        if self.opened.take().is_some() {
+            info!("sink circuit breaker closed, probe write succeeded");
+        }
+        self.failures = 0;
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        self.failures = self.failures.saturating_add(1);
+        if self.threshold == 0 {
+            return;
+        }
+        if self.opened.is_some() {
+            warn!("sink circuit breaker probe failed, open for another {:?}", self.cooldown);
+            self.opened = Some(now);
+        } else if self.failures >= self.threshold {
+            warn!(
+                "sink circuit breaker open after {} failed writes, failing fast for {:?}",
+                self.failures,
+                self.cooldown
+            );
+            self.opened = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn opens_after_threshold_failures_in_a_row() {
+        let now = Instant::now();
+        let mut breaker = Breaker::new(2, COOLDOWN);
+        breaker.record_failure(now);
+        breaker.record_success();
+        breaker.record_failure(now);
+        assert!(!breaker.is_open(now));
+        breaker.record_failure(now);
+        assert!(breaker.is_open(now));
+        assert!(!breaker.is_half_open(now));
+    }
+
+    #[test]
+    fn half_opens_after_cooldown_and_probe_decides() {
+        let start = Instant::now();
+        let mut breaker = Breaker::new(1, COOLDOWN);
+        breaker.record_failure(start);
+
+        let cooled = start + COOLDOWN;
+        assert!(breaker.is_half_open(cooled));
+        breaker.record_failure(cooled);
+        assert!(breaker.is_open(cooled + Duration::from_secs(1)));
+
+        let cooled = cooled + COOLDOWN;
+        assert!(breaker.is_half_open(cooled));
+        breaker.record_success();
+        assert!(!breaker.is_open(cooled) && !breaker.is_half_open(cooled));
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let now = Instant::now();
+        let mut breaker = Breaker::new(0, COOLDOWN);
+        for _ in 0..10 {
+            breaker.record_failure(now);
+        }
+        assert!(!breaker.is_open(now) && !breaker.is_half_open(now));
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use rumqttc::{ AsyncClient, Event, MqttOptions, Packet, QoS };
+use tokio::sync::mpsc::{ self, UnboundedReceiver };
+
+use crate::point::HomiePoint;
+
+/// A Homie `$broadcast` message, sent to every device under the base topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Broadcast {
+    /// the topic below `<base>/$broadcast/`, e.g. `alert`
+    pub subtopic: String,
+    pub message: String,
+}
+
+impl Broadcast {
+    /// The annotation point the broadcast is written as, kept apart from device values.
+    pub fn annotation(&self, timestamp: i64) -> HomiePoint {
+        HomiePoint::annotation("broadcast", &self.message, timestamp).with_tag(
+            "subtopic_tag",
+            &self.subtopic
+        )
+    }
+}
+
+/// Broadcasts under `base_topic`, which homie-controller doesn't pass on. They are read over
+/// a connection of their own, so `options` should use a client id of its own; it resubscribes
+/// after every reconnect.
+pub fn subscribe(options: MqttOptions, base_topic: &str) -> UnboundedReceiver<Broadcast> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let base_topic = base_topic.to_string();
+    let filter = format!("{}/$broadcast/#", base_topic);
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    debug!("subscribing to {}", filter);
+                    if let Err(e) = client.try_subscribe(&filter, QoS::AtLeastOnce) {
+                        error!("failed to subscribe to {}: {}", filter, e);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let broadcast = parse(&base_topic, &publish.topic, &publish.payload);
+                    let Some(broadcast) = broadcast else {
+                        trace!("ignoring {}", publish.topic);
+                        continue;
+                    };
+                    if tx.send(broadcast).is_err() {
+                        // the main loop is gone
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("broadcast subscription: {}, reconnecting", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// The broadcast published on `topic`, if it is one under `base_topic`.
+fn parse(base_topic: &str, topic: &str, payload: &[u8]) -> Option<Broadcast> {
+    let subtopic = topic.strip_prefix(base_topic)?.strip_prefix("/$broadcast/")?;
+    if subtopic.is_empty() {
+        return None;
+    }
+    Some(Broadcast {
+        subtopic: subtopic.to_string(),
+        message: String::from_utf8_lossy(payload).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::{ FieldValue, ANNOTATION_MEASUREMENT };
+
+    #[test]
+    fn parses_broadcasts_under_the_base_topic() {
+        let broadcast = parse("homie", "homie/$broadcast/alert", b"tornado warning").unwrap();
+        assert_eq!(broadcast.subtopic, "alert");
+        assert_eq!(broadcast.message, "tornado warning");
+        let nested = parse("homie", "homie/$broadcast/alerts/weather", b"").unwrap();
+        assert_eq!(nested.subtopic, "alerts/weather");
+    }
+
+    #[test]
+    fn ignores_other_topics() {
+        assert_eq!(parse("homie", "homie/thermostat/zone/temperature", b"21"), None);
+        assert_eq!(parse("homie", "other/$broadcast/alert", b"x"), None);
+        assert_eq!(parse("homie", "homie/$broadcast/", b"x"), None);
+        assert_eq!(parse("home", "homie/$broadcast/alert", b"x"), None);
+    }
+
+    #[test]
+    fn broadcast_becomes_an_annotation() {
+        let broadcast = Broadcast {
+            subtopic: "alert".to_string(),
+            message: "tornado warning".to_string(),
+        };
+        let point = broadcast.annotation(1_700_000_000);
+        assert_eq!(point.measurement, ANNOTATION_MEASUREMENT);
+        assert_eq!(point.tag("kind"), Some("broadcast"));
+        assert_eq!(point.tag("subtopic_tag"), Some("alert"));
+        let text = FieldValue::Text("tornado warning".to_string());
+        assert_eq!(point.fields, vec![("text".to_string(), text)]);
+        assert_eq!(point.timestamp, 1_700_000_000);
+    }
+}
@@ -0,0 +1,35 @@
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+
+/// Per-series state keyed by series path, bounded so churny device ids can't grow it without
+/// limit. Evicting a series just loses its state; it starts over the next time it is seen.
+pub type SeriesCache<V> = LruCache<String, V>;
+
+pub fn series_cache<V>(capacity: usize) -> SeriesCache<V> {
+    LruCache::new(NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_series() {
+        let mut cache = series_cache(2);
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+        cache.get("a");
+        cache.put("c".to_string(), 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&1));
+    }
+
+    #[test]
+    fn zero_capacity_still_holds_one_series() {
+        let mut cache = series_cache(0);
+        cache.put("a".to_string(), 1);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.cap().get(), 1);
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{ Deserialize, Serialize };
+
+/// Forward a property only while another property of the same device, given as
+/// `node/property`, satisfies every test set here. Until that property has been seen the
+/// condition doesn't hold.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub property: String,
+    /// the raw value, e.g. a mode, is exactly this
+    pub equals: Option<String>,
+    /// the raw value is one of these
+    pub one_of: Option<Vec<String>>,
+    /// the converted value is greater than this
+    pub above: Option<f64>,
+    /// the converted value is less than this
+    pub below: Option<f64>,
+}
+
+impl Condition {
+    /// Whether the condition holds for the gating property's latest raw and converted value.
+    pub fn holds(&self, raw: Option<&str>, value: Option<f64>) -> bool {
+        let (Some(raw), Some(value)) = (raw, value) else {
+            return false;
+        };
+        self.equals.iter().all(|equals| raw == equals) &&
+            self.one_of.iter().all(|one_of| one_of.iter().any(|v| v == raw)) &&
+            self.above.iter().all(|&above| value > above) &&
+            self.below.iter().all(|&below| value < below)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(contents: &str) -> Condition {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn unseen_property_does_not_hold() {
+        let condition = condition("property = 'hvac/mode'");
+        assert!(!condition.holds(None, None));
+        assert!(condition.holds(Some("heat"), Some(1.0)));
+    }
+
+    #[test]
+    fn raw_value_tests() {
+        let equals = condition("property = 'hvac/mode'\nequals = 'heat'");
+        assert!(equals.holds(Some("heat"), Some(1.0)));
+        assert!(!equals.holds(Some("cool"), Some(2.0)));
+
+        let one_of = condition("property = 'hvac/mode'\none_of = ['heat', 'auto']");
+        assert!(one_of.holds(Some("auto"), Some(3.0)));
+        assert!(!one_of.holds(Some("off"), Some(0.0)));
+    }
+
+    #[test]
+    fn converted_value_tests_are_exclusive() {
+        let range = condition("property = 'zone/temperature'\nabove = 10.0\nbelow = 30.0");
+        assert!(range.holds(Some("21.5"), Some(21.5)));
+        assert!(!range.holds(Some("10"), Some(10.0)));
+        assert!(!range.holds(Some("30"), Some(30.0)));
+    }
+}
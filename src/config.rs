@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+
+use crate::alias::Aliases;
+use crate::condition::Condition;
+use crate::convert::{ BooleanConfig, Calibration, Invalid };
+use crate::device_clock::TimestampSource;
+use crate::device_tags::DeviceTagRule;
+use crate::extract::Extract;
+use crate::point::{ IntegerEncoding, MetricType };
+use crate::rate::Rate;
+use crate::sampling::Sampling;
+use crate::setpoint::TargetStatePair;
+use crate::smoothing::Smoothing;
+use crate::static_metrics::StaticMetric;
+
+/// Settings loaded from the optional `--config` TOML file. Per-property tables are keyed
+/// by `device/node/property`, `node/property` or just `property`, most specific first.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    pub smoothing: HashMap<String, SmoothingConfig>,
+    pub redact: Vec<RedactRule>,
+    /// properties written as integer fields instead of floats
+    pub integer_fields: HashMap<String, IntegerEncoding>,
+    /// fixed decimation of high-frequency properties
+    pub sampling: HashMap<String, Sampling>,
+    /// target/state property pairs a `target_vs_state_delta` is written for
+    pub target_state: Vec<TargetStatePair>,
+    /// cumulative counters a `<property>_rate` metric is derived for
+    pub rate: HashMap<String, Rate>,
+    /// Influx bucket per measurement, read at startup; others go to --influx-bucket
+    pub buckets: HashMap<String, String>,
+    /// tags derived from device metadata
+    pub device_tags: Vec<DeviceTagRule>,
+    /// inverted and tri-state boolean properties
+    pub boolean: HashMap<String, BooleanConfig>,
+    /// value and timestamp extraction from structured payloads
+    pub extract: HashMap<String, Extract>,
+    /// the one mapping table consulted for a property's non-numeric values
+    pub tables: HashMap<String, String>,
+    /// the order mapping tables are consulted in for properties without a table
+    pub table_order: Vec<String>,
+    /// properties only forwarded while another property's value satisfies a condition
+    pub when: HashMap<String, Condition>,
+    /// properties that are counters rather than gauges
+    pub metric_types: HashMap<String, MetricType>,
+    /// what properties write for unconvertible and non-finite values
+    pub invalid: HashMap<String, Invalid>,
+    /// constant points written at startup, and by --resample-interval with the quiet series
+    #[serde(rename = "static")]
+    pub statics: HashMap<String, StaticMetric>,
+    /// friendlier device, node and property ids written in place of the raw ones
+    pub alias: Aliases,
+    /// linear corrections of converted values
+    pub calibration: HashMap<String, Calibration>,
+    /// per device id, the property carrying the device's clock, which stamps its other values
+    pub timestamp_source: HashMap<String, TimestampSource>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SmoothingConfig {
+    #[serde(flatten)]
+    pub method: Smoothing,
+    /// also write the unsmoothed value, tagged `series=raw`
+    #[serde(default)]
+    pub emit_raw: bool,
+}
+
+/// A regex applied to device/node/property ids; capture groups may be used in the replacement.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RedactRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {0}: {1}")] Read(String, std::io::Error),
+    #[error("failed to parse config file {0}: {1}")] Parse(String, toml::de::Error),
+    #[error("unknown key(s) in config file {0}: {1}")] UnknownKeys(String, String),
+    #[error("failed to fetch config from {0}, no cached copy: {1}")] Fetch(String, reqwest::Error),
+}
+
+impl Config {
+    /// Load the config from a file path or an `http(s)://` URL. A fetched config is cached
+    /// at `cache` and used in its place when the URL is unreachable on a later start. Unknown
+    /// keys are logged, or rejected if `strict`.
+    pub async fn load(source: &str, cache: &Path, strict: bool) -> Result<Config, ConfigError> {
+        if !source.starts_with("http://") && !source.starts_with("https://") {
+            let contents = fs
+                ::read_to_string(source)
+                .map_err(|e| ConfigError::Read(source.to_string(), e))?;
+            return Config::parse(source, &contents, strict);
+        }
+
+        match fetch(source).await {
+            Ok(contents) => {
+                let config = Config::parse(source, &contents, strict)?;
+                // only cache a config that parsed, so a bad fetch can't poison the fallback
+                if let Err(e) = fs::write(cache, &contents) {
+                    warn!("failed to cache config at {}: {}", cache.display(), e);
+                }
+                Ok(config)
+            }
+            Err(e) => {
+                warn!("failed to fetch config from {}: {}, using cached copy", source, e);
+                let contents = fs
+                    ::read_to_string(cache)
+                    .map_err(|_| ConfigError::Fetch(source.to_string(), e))?;
+                Config::parse(&cache.display().to_string(), &contents, strict)
+            }
+        }
+    }
+
+    /// Names of the sections that differ from `other`, for logging a reload.
+    pub fn changed_sections(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.smoothing != other.smoothing {
+            changed.push("smoothing");
+        }
+        if self.redact != other.redact {
+            changed.push("redact");
+        }
+        if self.integer_fields != other.integer_fields {
+            changed.push("integer_fields");
+        }
+        if self.sampling != other.sampling {
+            changed.push("sampling");
+        }
+        if self.target_state != other.target_state {
+            changed.push("target_state");
+        }
+        if self.rate != other.rate {
+            changed.push("rate");
+        }
+        if self.buckets != other.buckets {
+            // the sink's routes are built once at startup
+            warn!("bucket routing changed, restart to apply it");
+            changed.push("buckets");
+        }
+        if self.device_tags != other.device_tags {
+            changed.push("device_tags");
+        }
+        if self.boolean != other.boolean {
+            changed.push("boolean");
+        }
+        if self.extract != other.extract {
+            changed.push("extract");
+        }
+        if self.tables != other.tables {
+            changed.push("tables");
+        }
+        if self.table_order != other.table_order {
+            changed.push("table_order");
+        }
+        if self.when != other.when {
+            changed.push("when");
+        }
+        if self.metric_types != other.metric_types {
+            changed.push("metric_types");
+        }
+        if self.invalid != other.invalid {
+            changed.push("invalid");
+        }
+        if self.statics != other.statics {
+            changed.push("static");
+        }
+        if self.alias != other.alias {
+            changed.push("alias");
+        }
+        if self.calibration != other.calibration {
+            changed.push("calibration");
+        }
+        if self.timestamp_source != other.timestamp_source {
+            changed.push("timestamp_source");
+        }
+        changed
+    }
+
+    /// The properties configured as unsigned integer fields, sorted.
+    pub fn unsigned_fields(&self) -> Vec<&str> {
+        let mut unsigned: Vec<&str> = self.integer_fields
+            .iter()
+            .filter(|(_, encoding)| **encoding == IntegerEncoding::Unsigned)
+            .map(|(property, _)| property.as_str())
+            .collect();
+        unsigned.sort_unstable();
+        unsigned
+    }
+
+    fn parse(source: &str, contents: &str, strict: bool) -> Result<Config, ConfigError> {
+        let mut unknown = Vec::new();
+        let config: Config = serde_ignored
+            ::deserialize(toml::Deserializer::new(contents), |path| unknown.push(path.to_string()))
+            .map_err(|e| ConfigError::Parse(source.to_string(), e))?;
+        if strict && !unknown.is_empty() {
+            return Err(ConfigError::UnknownKeys(source.to_string(), unknown.join(", ")));
+        }
+        for key in &unknown {
+            warn!("ignoring unknown key {} in config file {}", key, source);
+        }
+        Ok(config)
+    }
+}
+
+async fn fetch(url: &str) -> Result<String, reqwest::Error> {
+    reqwest::Client
+        ::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send().await?
+        .error_for_status()?
+        .text().await
+}
+
+/// Find the entry for a property, trying the full `device/node/property` path first and
+/// falling back to `node/property` and then `property`.
+pub fn lookup<'a, T>(
+    table: &'a HashMap<String, T>,
+    device_id: &str,
+    node_id: &str,
+    property_id: &str
+) -> Option<&'a T> {
+    if table.is_empty() {
+        return None;
+    }
+    table
+        .get(&format!("{}/{}/{}", device_id, node_id, property_id))
+        .or_else(|| table.get(&format!("{}/{}", node_id, property_id)))
+        .or_else(|| table.get(property_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn lists_unsigned_fields() {
+        let contents = r#"
+            [integer_fields]
+            "meter/energy/pulses" = "unsigned"
+            setpoint = "signed"
+            "meter/water/litres" = "unsigned"
+        "#;
+        let config = Config::parse("test", contents, true).unwrap();
+        assert_eq!(config.unsigned_fields(), vec!["meter/energy/pulses", "meter/water/litres"]);
+        assert!(Config::default().unsigned_fields().is_empty());
+    }
+
+    const TYPO: &str = r#"
+        [integer_fields]
+        setpoint = "signed"
+
+        [smoothnig]
+        temperature = { window = 3 }
+    "#;
+
+    #[test]
+    fn unknown_keys_are_ignored_when_lenient() {
+        let config = Config::parse("test", TYPO, false).unwrap();
+        assert_eq!(config.integer_fields.len(), 1);
+        assert!(config.smoothing.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_an_error_when_strict() {
+        let e = Config::parse("homie.toml", TYPO, true).unwrap_err();
+        assert!(matches!(&e, ConfigError::UnknownKeys(_, keys) if keys == "smoothnig"));
+        assert_eq!(e.to_string(), "unknown key(s) in config file homie.toml: smoothnig");
+    }
+
+    #[tokio::test]
+    async fn fetched_config_is_applied_and_cached() {
+        // a one-request server handing out a config
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/homie.toml", listener.local_addr().unwrap().port());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let body = "[integer_fields]\nsetpoint = \"signed\"\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        });
+        let cache = std::env::temp_dir().join(format!("homie-config-cache-{}.toml", std::process::id()));
+
+        let config = Config::load(&url, &cache, true).await.unwrap();
+        server.join().unwrap();
+        assert_eq!(config.integer_fields["setpoint"], IntegerEncoding::Signed);
+
+        // the server is gone now, the cached copy stands in
+        let cached = Config::load(&url, &cache, true).await.unwrap();
+        std::fs::remove_file(&cache).unwrap();
+        assert_eq!(cached, config);
+    }
+}
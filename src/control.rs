@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+
+use serde::{ Deserialize, Serialize };
+use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
+use tokio::net::{ UnixListener, UnixStream };
+
+use crate::reload::Reloader;
+
+/// What happens to points produced while the sink is paused.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PausePolicy {
+    /// hold points in memory and write them on resume
+    Buffer,
+    /// discard points
+    Drop,
+}
+
+/// Runtime state shared between the main loop and the control socket.
+#[derive(Debug, Default)]
+pub struct Control {
+    paused: AtomicBool,
+    pub written: AtomicU64,
+    pub failed: AtomicU64,
+    pub dropped: AtomicU64,
+    pub buffered: AtomicU64,
+    /// points written to the fallback file instead of the sink
+    pub fallback: AtomicU64,
+    /// points the sink reported dropping in partial writes
+    pub rejected: AtomicU64,
+    /// declared device and property attributes that changed mid-run
+    pub metadata_changes: AtomicU64,
+    /// values per conversion outcome, see `convert::Outcome::label`
+    conversions: Mutex<BTreeMap<String, u64>>,
+    /// polls since the last status line
+    polls: Mutex<PollStats>,
+    /// points written and failed per device since the last status line
+    device_writes: Mutex<BTreeMap<String, (u64, u64)>>,
+    /// poll errors per kind, see `error::poll_error_kind`
+    poll_errors: Mutex<BTreeMap<String, u64>>,
+}
+
+/// Poll cycles over a status interval: how long `controller.poll` took and how many events
+/// each returned.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PollStats {
+    pub polls: u64,
+    pub duration: Duration,
+    pub max_duration: Duration,
+    pub events: u64,
+    pub max_events: u64,
+}
+
+impl PollStats {
+    pub fn mean_duration_ms(&self) -> f64 {
+        (self.duration.as_secs_f64() * 1000.0) / (self.polls.max(1) as f64)
+    }
+
+    pub fn mean_events(&self) -> f64 {
+        (self.events as f64) / (self.polls.max(1) as f64)
+    }
+}
+
+impl Control {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn count(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn count_conversion(&self, outcome: &str) {
+        *self.conversions.lock().unwrap().entry(outcome.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_poll(&self, duration: Duration, events: usize) {
+        let mut polls = self.polls.lock().unwrap();
+        polls.polls += 1;
+        polls.duration += duration;
+        polls.max_duration = polls.max_duration.max(duration);
+        polls.events += events as u64;
+        polls.max_events = polls.max_events.max(events as u64);
+    }
+
+    pub fn count_poll_error(&self, kind: &str) {
+        *self.poll_errors.lock().unwrap().entry(kind.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn poll_errors(&self) -> BTreeMap<String, u64> {
+        self.poll_errors.lock().unwrap().clone()
+    }
+
+    pub fn count_device_writes(&self, device_id: &str, written: u64, failed: u64) {
+        let mut devices = self.device_writes.lock().unwrap();
+        let counts = devices.entry(device_id.to_string()).or_default();
+        counts.0 += written;
+        counts.1 += failed;
+    }
+
+    /// The points written and failed per device since the last call, starting over.
+    pub fn take_device_writes(&self) -> BTreeMap<String, (u64, u64)> {
+        std::mem::take(&mut *self.device_writes.lock().unwrap())
+    }
+
+    /// The poll stats since the last call, starting over.
+    pub fn take_poll_stats(&self) -> PollStats {
+        std::mem::take(&mut *self.polls.lock().unwrap())
+    }
+
+    pub fn conversion_stats(&self) -> String {
+        self.conversions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(outcome, count)| format!("{}={}", outcome, count))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn stats(&self) -> String {
+        let poll_errors = self.poll_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(kind, count)| format!("poll_error_{}={}", kind, count))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "paused={} written={} failed={} dropped={} buffered={} fallback={} rejected={} metadata_changes={} {} {}",
+            self.is_paused(),
+            self.written.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+            self.buffered.load(Ordering::Relaxed),
+            self.fallback.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+            self.metadata_changes.load(Ordering::Relaxed),
+            poll_errors,
+            self.conversion_stats()
+        )
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// All counters as JSON, for `--metrics-out`.
+    pub fn snapshot(&self, sink: &str, uptime: Duration) -> serde_json::Value {
+        serde_json::json!({
+            "sink": sink,
+            "uptime_secs": uptime.as_secs_f64(),
+            "paused": self.is_paused(),
+            "written": self.written.load(Ordering::Relaxed),
+            "failed": self.failed.load(Ordering::Relaxed),
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "buffered": self.buffered.load(Ordering::Relaxed),
+            "fallback": self.fallback.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "metadata_changes": self.metadata_changes.load(Ordering::Relaxed),
+            "conversions": self.conversions.lock().unwrap().clone(),
+            "poll_errors": self.poll_errors(),
+        })
+    }
+
+    /// Write the [`Control::snapshot`] to `path` as pretty JSON, for `--metrics-out`.
+    pub fn write_snapshot(&self, sink: &str, uptime: Duration, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, format!("{:#}\n", self.snapshot(sink, uptime)))
+    }
+
+    /// Run one command line against the `sink` the bridge is writing to, returning the reply.
+    pub fn execute(&self, sink: &str, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["pause", name] | ["resume", name] if *name != sink => {
+                format!("error: unknown sink {}, writing to {}", name, sink)
+            }
+            ["pause", _] => {
+                self.paused.store(true, Ordering::Relaxed);
+                format!("ok: paused {}", sink)
+            }
+            ["resume", _] => {
+                self.paused.store(false, Ordering::Relaxed);
+                format!("ok: resumed {}", sink)
+            }
+            ["stats"] => self.stats(),
+            _ =>
+                format!(
+                    "error: unknown command {:?}, expected pause|resume <sink>, stats or reload",
+                    command
+                ),
+        }
+    }
+}
+
+/// Accept connections on a unix socket at `path`, answering one reply line per command line.
+/// `reload` goes through `reloader`, the others through [`Control::execute`].
+pub async fn serve(
+    path: &Path,
+    sink: String,
+    control: Arc<Control>,
+    reloader: Option<Reloader>
+) -> std::io::Result<()> {
+    // a socket left behind by an earlier run would make bind fail
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    info!("control socket listening on {}", path.display());
+
+    loop {
+        let (stream, _addr) = listener.accept().await?;
+        let sink = sink.clone();
+        let control = control.clone();
+        let reloader = reloader.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &sink, &control, reloader.as_ref()).await {
+                warn!("control socket: connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    stream: UnixStream,
+    sink: &str,
+    control: &Control,
+    reloader: Option<&Reloader>
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let reply = match (line.trim(), reloader) {
+            ("reload", None) => "error: no --config to reload".to_string(),
+            ("reload", Some(reloader)) =>
+                match reloader.reload().await {
+                    Ok(()) => format!("ok: reloaded {}", reloader.source),
+                    Err(e) => format!("error: reload failed, keeping the current config: {}", e),
+                }
+            (command, _) => control.execute(sink, command),
+        };
+        info!("control socket: {} -> {}", line.trim(), reply);
+        writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pauses_and_resumes_only_its_own_sink() {
+        let control = Control::default();
+        let reply = control.execute("influx", "pause telegraf");
+        assert_eq!(reply, "error: unknown sink telegraf, writing to influx");
+        assert!(!control.is_paused());
+        assert_eq!(control.execute("influx", "pause influx"), "ok: paused influx");
+        assert!(control.is_paused());
+        assert_eq!(control.execute("influx", " resume  influx "), "ok: resumed influx");
+        assert!(!control.is_paused());
+        assert!(control.execute("influx", "restart").starts_with("error: unknown command"));
+    }
+
+    #[test]
+    fn stats_list_every_counter() {
+        let control = Control::default();
+        Control::count(&control.written, 3);
+        control.count_conversion("parsed_float");
+        control.count_conversion("parsed_float");
+        control.count_poll_error("io");
+        assert_eq!(
+            control.execute("influx", "stats"),
+            "paused=false written=3 failed=0 dropped=0 buffered=0 fallback=0 rejected=0 metadata_changes=0 poll_error_io=1 parsed_float=2"
+        );
+        let snapshot = control.snapshot("influx", Duration::from_secs(5));
+        assert_eq!(snapshot["written"], 3);
+        assert_eq!(snapshot["conversions"]["parsed_float"], 2);
+        assert_eq!(snapshot["poll_errors"]["io"], 1);
+    }
+
+    #[test]
+    fn snapshot_file_holds_the_counters() {
+        let control = Control::default();
+        Control::count(&control.written, 7);
+        Control::count(&control.dropped, 2);
+        control.count_conversion("mapped");
+        let path = std::env::temp_dir().join(format!("homie-metrics-{}.json", std::process::id()));
+        control.write_snapshot("influx", Duration::from_secs(90), &path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let snapshot: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(snapshot["sink"], "influx");
+        assert_eq!(snapshot["uptime_secs"], 90.0);
+        assert_eq!(snapshot["written"], 7);
+        assert_eq!(snapshot["dropped"], 2);
+        assert_eq!(snapshot["conversions"]["mapped"], 1);
+    }
+
+    #[test]
+    fn poll_and_device_stats_start_over_when_taken() {
+        let control = Control::default();
+        control.record_poll(Duration::from_millis(10), 4);
+        control.record_poll(Duration::from_millis(30), 0);
+        let polls = control.take_poll_stats();
+        assert_eq!((polls.polls, polls.events, polls.max_events), (2, 4, 4));
+        assert_eq!(polls.mean_duration_ms(), 20.0);
+        assert_eq!(control.take_poll_stats().polls, 0);
+
+        control.count_device_writes("thermostat", 3, 1);
+        control.count_device_writes("thermostat", 1, 0);
+        assert_eq!(control.take_device_writes()["thermostat"], (4, 1));
+        assert!(control.take_device_writes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn answers_commands_on_the_socket() {
+        let path = std::env::temp_dir().join(format!("homie-control-{}.sock", std::process::id()));
+        let control = Arc::new(Control::default());
+        let server = {
+            let (path, control) = (path.clone(), control.clone());
+            tokio::spawn(async move { serve(&path, "influx".to_string(), control, None).await })
+        };
+        // the socket appears once the server is bound
+        let stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"pause influx\nreload\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok: paused influx");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "error: no --config to reload");
+        assert!(control.is_paused());
+
+        server.abort();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn reload_on_the_socket_picks_up_the_rewritten_config() {
+        let id = std::process::id();
+        let path = std::env::temp_dir().join(format!("homie-control-reload-{}.sock", id));
+        let config = std::env::temp_dir().join(format!("homie-control-reload-{}.toml", id));
+        std::fs::write(&config, "[alias.devices]\nthermostat-1 = \"hallway\"\n").unwrap();
+        let (reloads, mut reloaded) = tokio::sync::mpsc::unbounded_channel();
+        let reloader = Reloader {
+            source: config.display().to_string(),
+            cache: std::env::temp_dir().join("homie-control-reload-unused-cache.toml"),
+            strict: true,
+            reloads,
+        };
+        let server = {
+            let path = path.clone();
+            let control = Arc::new(Control::default());
+            tokio::spawn(async move { serve(&path, "influx".to_string(), control, Some(reloader)).await })
+        };
+        let stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        std::fs::write(&config, "[alias.devices]\nthermostat-1 = \"kitchen\"\n").unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"reload\n").await.unwrap();
+        let reply = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(reply, format!("ok: reloaded {}", config.display()));
+        let aliases = reloaded.try_recv().unwrap().config.alias;
+        assert_eq!(aliases.devices["thermostat-1"], "kitchen");
+
+        // a broken rewrite is refused and nothing is queued
+        std::fs::write(&config, "[alias.devices]\nthermostat-1 = \n").unwrap();
+        writer.write_all(b"reload\n").await.unwrap();
+        let reply = lines.next_line().await.unwrap().unwrap();
+        assert!(reply.starts_with("error: reload failed, keeping the current config"), "{}", reply);
+        assert!(reloaded.try_recv().is_err());
+
+        server.abort();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&config);
+    }
+}
@@ -0,0 +1,461 @@
+use serde::{ Deserialize, Serialize };
+
+/// A user-supplied converter, returning `None` for values it doesn't handle.
+pub type ConvertFn = Box<dyn Fn(&str) -> Option<f64>>;
+
+/// A built-in table, `None` for values it doesn't know.
+type TableFn = fn(&str) -> Option<f64>;
+
+/// Built-in tables for non-numeric values, consulted in this order.
+const BUILTIN_TABLES: &[(&str, TableFn)] = &[
+    ("boolean", boolean_to_value),
+    ("current_mode", current_mode_to_value),
+    ("humidifier_mode", humidifier_mode_to_value),
+    ("target_mode", target_mode_to_value),
+    ("target_fan_mode", target_fan_mode_to_value),
+    ("zone_priority", zone_priority_to_value),
+];
+
+/// Which conversion produced a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// the value parsed as a float
+    Parsed,
+    /// the value matched the table or registered converter of this category
+    Mapped(String),
+}
+
+impl Outcome {
+    /// Counter name for this outcome.
+    pub fn label(&self) -> String {
+        match self {
+            Outcome::Parsed => "parsed_float".to_string(),
+            Outcome::Mapped(category) if category == "boolean" => "boolean_synonym".to_string(),
+            Outcome::Mapped(category) if category == BOOLEAN_UNKNOWN => BOOLEAN_UNKNOWN.to_string(),
+            Outcome::Mapped(category) if category == UNKNOWN_MODE => UNKNOWN_MODE.to_string(),
+            Outcome::Mapped(category) => format!("mode_table_{}", category),
+        }
+    }
+
+    /// Whether a mode table or registered converter, rather than parsing or a boolean
+    /// mapping, produced the value.
+    pub fn is_enum(&self) -> bool {
+        match self {
+            Outcome::Mapped(category) => category != "boolean" && category != BOOLEAN_UNKNOWN,
+            Outcome::Parsed => false,
+        }
+    }
+
+    /// Code written in the `src` field with `--emit-provenance`.
+    pub fn provenance(&self) -> i64 {
+        match self {
+            Outcome::Parsed => 0,
+            Outcome::Mapped(_) => 1,
+        }
+    }
+}
+
+/// Category of a value a boolean property couldn't map, written as its `unknown` value.
+const BOOLEAN_UNKNOWN: &str = "boolean_unknown";
+
+/// Per-property handling of boolean values, for contacts and relays wired the other way
+/// round or reporting a third state.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct BooleanConfig {
+    /// map true/open to 0.0 and false/closed to 1.0
+    #[serde(default)]
+    pub invert: bool,
+    /// value written for anything that isn't a boolean, instead of falling back to 0.0
+    pub unknown: Option<f64>,
+}
+
+impl BooleanConfig {
+    /// Adjust the result of [`Converter::convert`] for a property with this config.
+    pub fn apply(&self, converted: Option<(f64, Outcome)>) -> Option<(f64, Outcome)> {
+        match converted {
+            Some((val, Outcome::Mapped(category))) if category == "boolean" && self.invert => {
+                Some((1.0 - val, Outcome::Mapped(category)))
+            }
+            None => self.unknown.map(|val| (val, Outcome::Mapped(BOOLEAN_UNKNOWN.to_string()))),
+            converted => converted,
+        }
+    }
+}
+
+/// A linear correction for a sensor that reads consistently high or low, `value * scale + offset`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    #[serde(default = "unit_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+impl Calibration {
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+}
+
+/// What a property writes for a value that doesn't convert, or converts to NaN or infinity.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase", tag = "action")]
+pub enum Invalid {
+    /// write 0.0
+    #[default]
+    Zero,
+    /// write nothing
+    Skip,
+    /// write `value`, e.g. -999, for dashboards to alert on
+    Sentinel {
+        value: f64,
+    },
+    /// write the point without a value field, or not at all if it has no other fields
+    Null,
+}
+
+/// Counter for invalid values written as a configured sentinel.
+pub const SENTINEL: &str = "sentinel";
+
+/// Counter for invalid values written without a value field, or not at all.
+pub const NULL: &str = "null";
+
+/// Provenance code of a value that fell back to 0.0.
+pub const PROVENANCE_DEFAULT: i64 = 2;
+
+/// Counter for values that fell through every conversion and were written as 0.0.
+pub const FALLBACK_ZERO: &str = "fallback_zero";
+
+/// Counter for values that were not written at all.
+pub const SKIPPED: &str = "skipped";
+
+/// Counter for decreases of a counter property, taken as resets.
+pub const COUNTER_RESET: &str = "counter_reset";
+
+/// Counter for values outside the range declared in their property's `$format`.
+pub const OUT_OF_RANGE: &str = "out_of_range";
+
+/// Category, and counter, of unknown modes given an index from `--unknown-mode-index`.
+pub const UNKNOWN_MODE: &str = "unknown_mode";
+
+/// Counter for empty values, however `--empty-value` handled them.
+pub const EMPTY: &str = "empty";
+
+/// Turns raw Homie property values into numbers. Converters registered with
+/// [`Converter::register_converter`] are consulted before the built-in tables.
+#[derive(Default)]
+pub struct Converter {
+    custom: Vec<(String, ConvertFn)>,
+}
+
+impl Converter {
+    pub fn new() -> Converter {
+        Converter::default()
+    }
+
+    /// Register a converter under `category`; converters are tried in registration order,
+    /// before the built-in tables.
+    ///
+    /// ```
+    /// use homie_input::convert::{ Converter, Outcome };
+    ///
+    /// let mut converter = Converter::new();
+    /// converter.register_converter(
+    ///     "fan_speed",
+    ///     Box::new(|value| {
+    ///         match value {
+    ///             "low" => Some(1.0),
+    ///             "auto" => Some(2.0),
+    ///             "high" => Some(3.0),
+    ///             _ => None,
+    ///         }
+    ///     })
+    /// );
+    ///
+    /// let high = converter.convert("high", None, &[]);
+    /// assert_eq!(high, Some((3.0, Outcome::Mapped("fan_speed".to_string()))));
+    /// // consulted before the built-in mode tables, which map auto as well
+    /// let auto = converter.convert("auto", None, &[]);
+    /// assert_eq!(auto, Some((2.0, Outcome::Mapped("fan_speed".to_string()))));
+    /// // values it doesn't handle fall through to the built-ins
+    /// let heat = converter.convert("heat", None, &[]);
+    /// assert_eq!(heat, Some((4.0, Outcome::Mapped("target_mode".to_string()))));
+    /// ```
+    pub fn register_converter(&mut self, category: &str, f: ConvertFn) {
+        self.custom.push((category.to_string(), f));
+    }
+
+    /// Whether a registered converter or built-in table goes by `name`.
+    pub fn has_table(&self, name: &str) -> bool {
+        self.custom.iter().any(|(category, _)| category == name) ||
+            BUILTIN_TABLES.iter().any(|(category, _)| *category == name)
+    }
+
+    /// Convert a raw value: numbers parse directly, anything else goes through `table` if
+    /// given, otherwise the tables named in `order`, then the remaining registered converters
+    /// and built-in tables in their own order.
+    pub fn convert(
+        &self,
+        value: &str,
+        table: Option<&str>,
+        order: &[String]
+    ) -> Option<(f64, Outcome)> {
+        if let Ok(val) = value.parse() {
+            return Some((val, Outcome::Parsed));
+        }
+
+        let custom = self.custom
+            .iter()
+            .map(|(category, f)| (category.as_str(), Table::Custom(f)));
+        let builtin = BUILTIN_TABLES.iter().map(|(category, f)| (*category, Table::Builtin(*f)));
+        let mut tables: Vec<(&str, Table)> = custom
+            .chain(builtin)
+            .filter(|(category, _)| table.is_none() || table == Some(*category))
+            .collect();
+        if table.is_none() && !order.is_empty() {
+            // stable, so unlisted tables keep their order after the listed ones
+            tables.sort_by_key(|(category, _)| {
+                order.iter().position(|name| name == category).unwrap_or(order.len())
+            });
+        }
+
+        tables.into_iter().find_map(|(category, table)| {
+            let val = table.convert(value)?;
+            trace!("converted {} to {} using {}", value, val, category);
+            Some((val, Outcome::Mapped(category.to_string())))
+        })
+    }
+}
+
+/// A registered converter or a built-in table.
+enum Table<'a> {
+    Custom(&'a ConvertFn),
+    Builtin(fn(&str) -> Option<f64>),
+}
+
+impl Table<'_> {
+    fn convert(&self, value: &str) -> Option<f64> {
+        match self {
+            Table::Custom(f) => f(value),
+            Table::Builtin(f) => f(value),
+        }
+    }
+}
+
+fn boolean_to_value(s: &str) -> Option<f64> {
+    match s {
+        "true" | "open" => Some(1.0),
+        "false" | "closed" => Some(0.0),
+        _ => None,
+    }
+}
+
+fn in_zone_priority(s: &str) -> bool {
+    matches!(s, "economy" | "comfort")
+}
+
+fn in_current_mode(s: &str) -> bool {
+    matches!(
+        s,
+        "lockout" |
+            "standby" |
+            "blower" |
+            "heating" |
+            "heating_with_aux" |
+            "emergency_heat" |
+            "cooling" |
+            "waiting" |
+            "h1" |
+            "h2" |
+            "h3" |
+            "c1" |
+            "c2"
+    )
+}
+
+fn in_target_fan_mode(s: &str) -> bool {
+    matches!(s, "auto" | "continuous" | "intermittent")
+}
+
+fn in_target_mode(s: &str) -> bool {
+    matches!(s, "off" | "auto" | "cool" | "heat" | "eheat")
+}
+
+fn in_humidifier_mode(s: &str) -> bool {
+    matches!(s, "auto" | "manual")
+}
+
+fn current_mode_to_value(s: &str) -> Option<f64> {
+    if !in_current_mode(s) {
+        None
+    } else {
+        Some(match s {
+            "lockout" => 1f64,
+            "standby" => 2f64,
+            "blower" => 3f64,
+            "heating" => 4f64,
+            "heating_with_aux" => 5f64,
+            "emergency_heat" => 6f64,
+            "cooling" => 7f64,
+            "waiting" => 8f64,
+            "h1" => 2.1,
+            "h2" => 2.2,
+            "h3" => 2.3,
+            "c1" => 2.4,
+            "c2" => 2.5,
+            _ => 0f64,
+        })
+    }
+}
+
+fn humidifier_mode_to_value(s: &str) -> Option<f64> {
+    if !in_humidifier_mode(s) {
+        None
+    } else {
+        Some(match s {
+            "auto" => 1f64,
+            "manual" => 2f64,
+            _ => 0f64,
+        })
+    }
+}
+
+fn zone_priority_to_value(s: &str) -> Option<f64> {
+    if !in_zone_priority(s) {
+        None
+    } else {
+        Some(match s {
+            "economy" => 1f64,
+            "comfort" => 2f64,
+            _ => 0f64,
+        })
+    }
+}
+
+fn target_mode_to_value(s: &str) -> Option<f64> {
+    if !in_target_mode(s) {
+        None
+    } else {
+        Some(match s {
+            "off" => 1f64,
+            "auto" => 2f64,
+            "cool" => 3f64,
+            "heat" => 4f64,
+            "eheat" => 5f64,
+            _ => 0f64,
+        })
+    }
+}
+
+fn target_fan_mode_to_value(s: &str) -> Option<f64> {
+    if !in_target_fan_mode(s) {
+        None
+    } else {
+        Some(match s {
+            "auto" => 1f64,
+            "continuous" => 2f64,
+            "intermittent" => 3f64,
+            _ => 0f64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(category: &str) -> Outcome {
+        Outcome::Mapped(category.to_string())
+    }
+
+    #[test]
+    fn numbers_parse_directly() {
+        let converter = Converter::new();
+        assert_eq!(converter.convert("21.5", Some("target_mode"), &[]), Some((21.5, Outcome::Parsed)));
+        assert_eq!(converter.convert("nope", None, &[]), None);
+    }
+
+    #[test]
+    fn tables_are_tried_in_order() {
+        let converter = Converter::new();
+        // humidifier_mode comes before target_mode among the built-ins
+        assert_eq!(converter.convert("auto", None, &[]), Some((1.0, mapped("humidifier_mode"))));
+        let order = vec!["target_mode".to_string()];
+        assert_eq!(converter.convert("auto", None, &order), Some((2.0, mapped("target_mode"))));
+        assert_eq!(
+            converter.convert("auto", Some("target_fan_mode"), &order),
+            Some((1.0, mapped("target_fan_mode")))
+        );
+        // an assigned table is the only one consulted
+        assert_eq!(converter.convert("heat", Some("zone_priority"), &[]), None);
+    }
+
+    #[test]
+    fn intermittent_fan_mode_is_mapped() {
+        let converter = Converter::new();
+        assert_eq!(
+            converter.convert("intermittent", Some("target_fan_mode"), &[]),
+            Some((3.0, mapped("target_fan_mode")))
+        );
+    }
+
+    #[test]
+    fn knows_builtin_and_registered_tables() {
+        let mut converter = Converter::new();
+        assert!(converter.has_table("current_mode"));
+        assert!(!converter.has_table("fan_speed"));
+        converter.register_converter("fan_speed", Box::new(|_| Some(1.0)));
+        assert!(converter.has_table("fan_speed"));
+    }
+
+    #[test]
+    fn registered_converters_keep_f64_precision() {
+        let mut converter = Converter::new();
+        converter.register_converter("flow", Box::new(|_| Some(0.123_456_789_012_345_6)));
+        assert_eq!(converter.convert("steady", None, &[]), Some((0.123_456_789_012_345_6, mapped("flow"))));
+    }
+
+    #[test]
+    fn booleans_can_be_inverted_or_given_an_unknown_value() {
+        let converter = Converter::new();
+        let inverted = BooleanConfig { invert: true, unknown: None };
+        assert_eq!(inverted.apply(converter.convert("open", None, &[])), Some((0.0, mapped("boolean"))));
+        // only boolean mappings are inverted
+        assert_eq!(inverted.apply(Some((3.0, Outcome::Parsed))), Some((3.0, Outcome::Parsed)));
+
+        let unknown = BooleanConfig { invert: false, unknown: Some(-1.0) };
+        let ajar = converter.convert("ajar", None, &[]);
+        assert_eq!(unknown.apply(ajar), Some((-1.0, mapped(BOOLEAN_UNKNOWN))));
+        assert_eq!(inverted.apply(None), None);
+    }
+
+    #[test]
+    fn outcome_labels() {
+        assert_eq!(Outcome::Parsed.label(), "parsed_float");
+        assert_eq!(mapped("boolean").label(), "boolean_synonym");
+        assert_eq!(mapped("target_mode").label(), "mode_table_target_mode");
+        assert!(mapped("target_mode").is_enum());
+        assert!(!mapped("boolean").is_enum());
+        assert_eq!(mapped("target_mode").provenance(), 1);
+    }
+
+    #[test]
+    fn calibration_scales_then_offsets() {
+        let calibration: Calibration = toml::from_str("offset = -1.5").unwrap();
+        assert_eq!(calibration.apply(20.0), 18.5);
+        assert_eq!(Calibration { scale: 2.0, offset: 1.0 }.apply(3.0), 7.0);
+    }
+
+    #[test]
+    fn invalid_actions_parse_from_config() {
+        let sentinel: Invalid = toml::from_str("action = \"sentinel\"\nvalue = -999.0").unwrap();
+        assert_eq!(sentinel, Invalid::Sentinel { value: -999.0 });
+        let skip: Invalid = toml::from_str("action = \"skip\"").unwrap();
+        assert_eq!(skip, Invalid::Skip);
+        assert_eq!(Invalid::default(), Invalid::Zero);
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::{ File, OpenOptions };
+use std::io::{ self, Write };
+use std::path::Path;
+
+use chrono::Utc;
+
+use crate::point::HomiePoint;
+
+/// Records points that could not be written, with the reason, so nothing disappears
+/// without a trace. Entries go to a file when one is configured and otherwise to the
+/// `deadletter` log target at warn level.
+#[derive(Debug, Default)]
+pub struct Deadletter {
+    file: Option<File>,
+}
+
+impl Deadletter {
+    pub fn open(path: Option<&Path>) -> io::Result<Deadletter> {
+        let file = match path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(Deadletter { file })
+    }
+
+    pub fn record(&mut self, point: &HomiePoint, reason: &str) {
+        let line = point.to_line_protocol(None);
+        if let Some(file) = self.file.as_mut() {
+            // one tab-separated entry per line: when, why, the point as line protocol
+            let entry = format!("{}\t{}\t{}\n", Utc::now().to_rfc3339(), reason, line);
+            if let Err(e) = file.write_all(entry.as_bytes()) {
+                error!("failed to write deadletter entry: {}", e);
+            } else {
+                return;
+            }
+        }
+        warn!(target: "deadletter", "{} error=\"{}\"", line, reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::replay::parse_line_protocol;
+
+    #[test]
+    fn appends_replayable_entries() {
+        let path = std::env::temp_dir().join(format!("homie-deadletter-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        for reason in ["timeout", "sink paused"] {
+            let mut deadletter = Deadletter::open(Some(&path)).unwrap();
+            deadletter.record(&point, reason);
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let entries: Vec<Vec<&str>> = contents.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1][1], "sink paused");
+        assert_eq!(parse_line_protocol(entries[0][2]), Some(point));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn without_a_file_entries_are_logged() {
+        let mut deadletter = Deadletter::open(None).unwrap();
+        assert!(deadletter.file.is_none());
+        deadletter.record(&HomiePoint::new(1.0, "d", "n", "p", 0), "timeout");
+    }
+}
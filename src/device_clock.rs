@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::extract::parse_timestamp;
+
+/// A property, given as `node/property` within the device, whose value is the device's own
+/// clock, as epoch seconds or milliseconds, or RFC 3339.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TimestampSource {
+    pub property: String,
+    /// seconds after which the last reading is stale and values fall back to their own time
+    #[serde(default = "default_max_age")]
+    pub max_age_secs: i64,
+}
+
+fn default_max_age() -> i64 {
+    300
+}
+
+/// The last clock reading per device, with the time it arrived.
+#[derive(Debug, Default)]
+pub struct DeviceClocks {
+    readings: HashMap<String, (i64, i64)>,
+}
+
+impl DeviceClocks {
+    pub fn new() -> DeviceClocks {
+        DeviceClocks::default()
+    }
+
+    /// The timestamp for a value of `node_id/property_id` on `device_id` arriving at
+    /// `event_time`. A value of the source property itself is taken as a reading and keeps
+    /// `event_time`; others get the last reading unless it is stale or there is none.
+    pub fn timestamp(
+        &mut self,
+        source: &TimestampSource,
+        device_id: &str,
+        node_id: &str,
+        property_id: &str,
+        value: &str,
+        event_time: i64
+    ) -> i64 {
+        if source.property == format!("{}/{}", node_id, property_id) {
+            match parse_timestamp(value) {
+                Some(clock) => {
+                    self.readings.insert(device_id.to_string(), (clock, event_time));
+                }
+                None => {
+                    warn!("{} clock {} = {} isn't a timestamp", device_id, source.property, value);
+                }
+            }
+            return event_time;
+        }
+
+        match self.readings.get(device_id) {
+            Some((clock, read_at)) if event_time - read_at <= source.max_age_secs => *clock,
+            Some((_, read_at)) => {
+                debug!("{} clock is {}s old, using the event time", device_id, event_time - read_at);
+                event_time
+            }
+            None => event_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> TimestampSource {
+        toml::from_str("property = 'system/clock'").unwrap()
+    }
+
+    #[test]
+    fn values_get_the_last_clock_reading() {
+        let source = source();
+        let mut clocks = DeviceClocks::new();
+        assert_eq!(clocks.timestamp(&source, "meter", "main", "energy", "10", 2_000), 2_000);
+
+        assert_eq!(clocks.timestamp(&source, "meter", "system", "clock", "1700000000000", 2_000), 2_000);
+        assert_eq!(clocks.timestamp(&source, "meter", "main", "energy", "10", 2_010), 1_700_000_000);
+        // other devices keep their own time
+        assert_eq!(clocks.timestamp(&source, "heater", "main", "energy", "10", 2_010), 2_010);
+    }
+
+    #[test]
+    fn stale_or_unparsed_readings_fall_back_to_the_event_time() {
+        let source = source();
+        assert_eq!(source.max_age_secs, 300);
+        let mut clocks = DeviceClocks::new();
+        clocks.timestamp(&source, "meter", "system", "clock", "1700000000", 2_000);
+        assert_eq!(clocks.timestamp(&source, "meter", "main", "energy", "10", 2_300), 1_700_000_000);
+        assert_eq!(clocks.timestamp(&source, "meter", "main", "energy", "10", 2_301), 2_301);
+
+        let mut clocks = DeviceClocks::new();
+        clocks.timestamp(&source, "meter", "system", "clock", "soon", 2_000);
+        assert_eq!(clocks.timestamp(&source, "meter", "main", "energy", "10", 2_010), 2_010);
+    }
+}
@@ -0,0 +1,145 @@
+use homie_controller::Device;
+use regex::Regex;
+use serde::{ Deserialize, Serialize };
+
+/// Device attribute a tag rule matches against.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceAttribute {
+    Id,
+    Name,
+    Implementation,
+    FirmwareName,
+}
+
+/// A regex over a device attribute whose named capture groups become tags on every metric of
+/// the device, e.g. `^(?P<room>\w+) ` on `$name` for a `room` tag.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DeviceTagRule {
+    #[serde(default = "default_attribute")]
+    pub attribute: DeviceAttribute,
+    pub pattern: String,
+}
+
+fn default_attribute() -> DeviceAttribute {
+    DeviceAttribute::Name
+}
+
+/// Derives tags from device metadata with the configured rules.
+#[derive(Debug, Default)]
+pub struct DeviceTagger {
+    rules: Vec<(DeviceAttribute, Regex)>,
+}
+
+impl DeviceTagger {
+    pub fn new(rules: &[DeviceTagRule]) -> Result<DeviceTagger, regex::Error> {
+        let rules = rules
+            .iter()
+            .map(|rule| Ok((rule.attribute, Regex::new(&rule.pattern)?)))
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+        Ok(DeviceTagger { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Tags captured from `device`'s attributes; attributes the device hasn't published yet
+    /// yield none.
+    pub fn tags(&self, device: &Device) -> Vec<(String, String)> {
+        let mut tags = Vec::new();
+        for (attribute, pattern) in &self.rules {
+            let value = match attribute {
+                DeviceAttribute::Id => Some(&device.id),
+                DeviceAttribute::Name => device.name.as_ref(),
+                DeviceAttribute::Implementation => device.implementation.as_ref(),
+                DeviceAttribute::FirmwareName => device.firmware_name.as_ref(),
+            };
+            let Some(captures) = value.and_then(|value| pattern.captures(value)) else {
+                continue;
+            };
+            for name in pattern.capture_names().flatten() {
+                if let Some(capture) = captures.name(name) {
+                    tags.push((name.to_string(), capture.as_str().to_string()));
+                }
+            }
+        }
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use homie_controller::State;
+
+    use super::*;
+
+    fn device(id: &str, name: Option<&str>) -> Device {
+        Device {
+            id: id.to_string(),
+            homie_version: "4.0".to_string(),
+            name: name.map(str::to_string),
+            state: State::Ready,
+            implementation: Some("esp8266".to_string()),
+            nodes: HashMap::new(),
+            extensions: Vec::new(),
+            local_ip: None,
+            mac: None,
+            firmware_name: None,
+            firmware_version: None,
+            stats_interval: None,
+            stats_uptime: None,
+            stats_signal: None,
+            stats_cputemp: None,
+            stats_cpuload: None,
+            stats_battery: None,
+            stats_freeheap: None,
+            stats_supply: None,
+        }
+    }
+
+    fn rule(attribute: DeviceAttribute, pattern: &str) -> DeviceTagRule {
+        DeviceTagRule { attribute, pattern: pattern.to_string() }
+    }
+
+    #[test]
+    fn named_captures_become_tags() {
+        let tagger = DeviceTagger::new(
+            &[
+                rule(DeviceAttribute::Name, r"^(?P<room>\w+) (?P<kind>\w+)$"),
+                rule(DeviceAttribute::Implementation, r"^(?P<platform>esp\d+)"),
+            ]
+        ).unwrap();
+        assert_eq!(tagger.tags(&device("thermostat-1", Some("kitchen thermostat"))), vec![
+            ("room".to_string(), "kitchen".to_string()),
+            ("kind".to_string(), "thermostat".to_string()),
+            ("platform".to_string(), "esp8266".to_string())
+        ]);
+    }
+
+    #[test]
+    fn unpublished_or_unmatched_attributes_give_no_tags() {
+        let tagger = DeviceTagger::new(
+            &[
+                rule(DeviceAttribute::Name, r"^(?P<room>\w+) "),
+                rule(DeviceAttribute::FirmwareName, "(?P<fw>.+)"),
+            ]
+        ).unwrap();
+        assert!(tagger.tags(&device("thermostat-1", None)).is_empty());
+        assert!(tagger.tags(&device("thermostat-1", Some("thermostat"))).is_empty());
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        assert!(DeviceTagger::new(&[rule(DeviceAttribute::Id, "(")]).is_err());
+        assert!(DeviceTagger::new(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn attribute_defaults_to_name() {
+        let rule: DeviceTagRule = toml::from_str("pattern = '(?P<room>\\w+)'").unwrap();
+        assert_eq!(rule.attribute, DeviceAttribute::Name);
+    }
+}
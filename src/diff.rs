@@ -0,0 +1,140 @@
+use std::io::{ self, BufRead };
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::control::Control;
+use crate::pipeline::{ self, Pipeline };
+
+/// Run sample values through pipelines built from the `old` and `new` configs, printing the
+/// samples whose points differ, and return how many did. Samples are `device/node/property
+/// value` lines read from `samples`, or stdin; each is stamped one second after the last so
+/// rates and interval sampling behave the same under both configs.
+pub async fn diff_mappings(
+    old: &str,
+    new: &str,
+    samples: Option<&Path>,
+    cache: &Path,
+    strict: bool,
+    options: pipeline::Options
+) -> Result<usize, String> {
+    let load = |source: &str| {
+        let source = source.to_string();
+        async move { Config::load(&source, cache, strict).await.map_err(|e| e.to_string()) }
+    };
+    let mut old_pipeline = Pipeline::new(load(old).await?, options.clone(), Arc::new(Control::default()));
+    let mut new_pipeline = Pipeline::new(load(new).await?, options, Arc::new(Control::default()));
+
+    let lines: Vec<String> = match samples {
+        Some(path) => {
+            std::fs
+                ::read_to_string(path)
+                .map_err(|e| format!("failed to read samples {}: {}", path.display(), e))?
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+        None => io::stdin().lock().lines().collect::<Result<_, _>>().map_err(|e| e.to_string())?,
+    };
+
+    let mut changed = 0;
+    for (timestamp, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (path, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let ids: Vec<&str> = path.splitn(3, '/').collect();
+        let [device_id, node_id, property_id] = ids[..] else {
+            warn!("skipping sample {:?}, expected device/node/property value", line);
+            continue;
+        };
+
+        let render = |pipeline: &mut Pipeline| {
+            pipeline
+                .process(device_id, node_id, property_id, value.to_string(), timestamp as i64)
+                .iter()
+                .map(|point| point.to_line_protocol(None))
+                .collect::<Vec<_>>()
+        };
+        let before = render(&mut old_pipeline);
+        let after = render(&mut new_pipeline);
+        if before != after {
+            changed += 1;
+            println!("{} = {:?}", path, value);
+            for point in &before {
+                println!("  - {}", point);
+            }
+            for point in &after {
+                println!("  + {}", point);
+            }
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::pipeline::EmptyValue;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("homie-diff-{}-{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn options() -> pipeline::Options {
+        pipeline::Options {
+            trim: true,
+            dump_raw: false,
+            series_cache_size: 16,
+            empty_value: EmptyValue::Zero,
+            emit_provenance: false,
+            emit_raw_string: false,
+            enum_string_tag: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_the_samples_whose_points_differ() {
+        let old = temp_file("old.toml", "");
+        let new = temp_file("new.toml", "[rate]\nenergy = { scale = 1.0 }\n");
+        let samples = temp_file(
+            "samples",
+            concat!(
+                "# meter readings\nmeter/main/energy 10\nmeter/main/energy 12\n",
+                "\nthermostat/zone/temperature 21.5\nbogus 1\n"
+            )
+        );
+        let cache = std::env::temp_dir().join("homie-diff-unused-cache.toml");
+
+        let changed = diff_mappings(
+            &old.display().to_string(),
+            &new.display().to_string(),
+            Some(&samples),
+            &cache,
+            true,
+            options()
+        ).await;
+        for path in [old, new, samples] {
+            std::fs::remove_file(path).unwrap();
+        }
+        // only the second reading, which the new config derives a rate from
+        assert_eq!(changed, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn missing_samples_file_is_an_error() {
+        let old = temp_file("same.toml", "");
+        let source = old.display().to_string();
+        let cache = std::env::temp_dir().join("homie-diff-unused-cache.toml");
+        let missing = std::env::temp_dir().join("homie-diff-no-such-samples");
+
+        let result = diff_mappings(&source, &source, Some(&missing), &cache, true, options()).await;
+        std::fs::remove_file(old).unwrap();
+        assert!(result.unwrap_err().starts_with("failed to read samples"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ self, Write };
+use std::path::Path;
+
+/// Device ids seen so far, to annotate the first appearance of each. With a known-devices
+/// file they are remembered across restarts, otherwise every device is new again.
+#[derive(Debug, Default)]
+pub struct Discovery {
+    known: HashSet<String>,
+    // one device id per line, appended as they are discovered
+    file: Option<File>,
+}
+
+impl Discovery {
+    pub fn new() -> Discovery {
+        Discovery::default()
+    }
+
+    /// Remember discovered devices in `path`, taking those already in it as known.
+    pub fn open(path: &Path) -> io::Result<Discovery> {
+        let known = match fs::read_to_string(path) {
+            Ok(contents) =>
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Discovery { known, file: Some(file) })
+    }
+
+    /// Whether `device_id` is seen for the first time, remembering it if so.
+    pub fn discover(&mut self, device_id: &str) -> bool {
+        if !self.known.insert(device_id.to_string()) {
+            return false;
+        }
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = writeln!(file, "{}", device_id) {
+                error!("failed to remember device {}: {}", device_id, e);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_device_is_new_once() {
+        let mut discovery = Discovery::new();
+        assert!(discovery.discover("thermostat"));
+        assert!(!discovery.discover("thermostat"));
+        assert!(discovery.discover("heater"));
+    }
+
+    #[test]
+    fn known_devices_file_survives_a_restart() {
+        let path = std::env::temp_dir().join(format!("homie-known-devices-{}", std::process::id()));
+        let mut discovery = Discovery::open(&path).unwrap();
+        assert!(discovery.discover("thermostat"));
+        drop(discovery);
+
+        let mut reopened = Discovery::open(&path).unwrap();
+        assert!(!reopened.discover("thermostat"));
+        assert!(reopened.discover("heater"));
+        drop(reopened);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "thermostat\nheater\n");
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use crate::control::Control;
+use crate::convert;
+use crate::pipeline::PropertyChange;
+
+/// Forwards a device's values only while its enable property, e.g. a settable
+/// `telemetry/enabled`, is on. The property is only read, never set; a device that hasn't
+/// published it is forwarded.
+#[derive(Debug)]
+pub struct EnableGate {
+    node_id: String,
+    property_id: String,
+    disabled: HashSet<String>,
+}
+
+impl EnableGate {
+    /// A gate on the `node/property` given, or `None` if it isn't one.
+    pub fn new(property: &str) -> Option<EnableGate> {
+        let (node_id, property_id) = property.split_once('/')?;
+        if node_id.is_empty() || property_id.is_empty() || property_id.contains('/') {
+            return None;
+        }
+        Some(EnableGate {
+            node_id: node_id.to_string(),
+            property_id: property_id.to_string(),
+            disabled: HashSet::new(),
+        })
+    }
+
+    /// The changes of enabled devices, in order. A change of the enable property switches its
+    /// device for the changes after it and is itself always forwarded.
+    pub fn pass(&mut self, changes: Vec<PropertyChange>, control: &Control) -> Vec<PropertyChange> {
+        changes
+            .into_iter()
+            .filter(|change| {
+                if change.node_id == self.node_id && change.property_id == self.property_id {
+                    let enabled = !is_off(&change.value);
+                    let switched = if enabled {
+                        self.disabled.remove(&change.device_id)
+                    } else {
+                        self.disabled.insert(change.device_id.clone())
+                    };
+                    if switched {
+                        info!(
+                            "{} forwarding for {}: {}/{} = {}",
+                            if enabled { "enabled" } else { "disabled" },
+                            change.device_id,
+                            self.node_id,
+                            self.property_id,
+                            change.value.trim()
+                        );
+                    }
+                    return true;
+                }
+                if self.disabled.contains(&change.device_id) {
+                    control.count_conversion(convert::SKIPPED);
+                    return false;
+                }
+                true
+            })
+            .collect()
+    }
+}
+
+fn is_off(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "false" | "0" | "off" | "no" | "disabled")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(device_id: &str, node_id: &str, property_id: &str, value: &str) -> PropertyChange {
+        PropertyChange {
+            device_id: device_id.to_string(),
+            node_id: node_id.to_string(),
+            property_id: property_id.to_string(),
+            value: value.to_string(),
+            event_time: 0,
+        }
+    }
+
+    fn values(changes: &[PropertyChange]) -> Vec<&str> {
+        changes.iter().map(|change| change.value.as_str()).collect()
+    }
+
+    #[test]
+    fn only_node_slash_property_is_a_gate() {
+        assert!(EnableGate::new("telemetry/enabled").is_some());
+        assert!(EnableGate::new("enabled").is_none());
+        assert!(EnableGate::new("/enabled").is_none());
+        assert!(EnableGate::new("a/b/c").is_none());
+    }
+
+    #[test]
+    fn disabled_devices_are_skipped_until_enabled() {
+        let mut gate = EnableGate::new("telemetry/enabled").unwrap();
+        let control = Control::default();
+        let changes = vec![
+            change("thermostat", "zone", "temperature", "1"),
+            change("thermostat", "telemetry", "enabled", " Off "),
+            change("thermostat", "zone", "temperature", "2"),
+            change("heater", "zone", "temperature", "3"),
+            change("thermostat", "telemetry", "enabled", "true"),
+            change("thermostat", "zone", "temperature", "4")
+        ];
+        assert_eq!(values(&gate.pass(changes, &control)), ["1", " Off ", "3", "true", "4"]);
+        assert_eq!(control.conversion_stats(), "skipped=1");
+    }
+}
@@ -0,0 +1,69 @@
+use homie_controller::PollError;
+use rumqttc::ConnectionError;
+use thiserror::Error;
+
+/// Errors parsing settings, connecting to a sink or writing to it.
+#[derive(Debug, Error)]
+pub enum BridgeError {
+    #[error("invalid push method {0:?}, expected influx, telegraf, victoriametrics or kafka")] InvalidPushMethod(String),
+    #[error("invalid url {0}: {1}")] InvalidUrl(String, url::ParseError),
+    #[error("failed to build http client: {0}")] HttpClient(reqwest::Error),
+    #[error("failed to connect to {0}: {1}")] Connect(String, String),
+    #[error("{0}: write failed: {1}")] Write(&'static str, String),
+    #[error("influx setup failed: {0}")] InfluxSetup(String),
+    #[error("missing environment variable {0}")] MissingEnv(&'static str),
+    #[error("{0} support isn't built in, rebuild with --features {0}")] NotBuilt(&'static str),
+    #[error(
+        "failed to reach telegraf at {0}: {1}; check that telegraf has a socket_listener input whose service_address has this port and transport (--tel-transport)"
+    )] Telegraf(String, String),
+}
+
+/// The kind of a poll error, as counted in the `poll_error_<kind>` counters.
+pub fn poll_error_kind(e: &PollError) -> &'static str {
+    match e {
+        PollError::Client(_) => "client",
+        PollError::Connection(e) =>
+            match e {
+                ConnectionError::MqttState(_) => "state",
+                ConnectionError::NetworkTimeout => "network_timeout",
+                ConnectionError::FlushTimeout => "flush_timeout",
+                ConnectionError::Io(_) => "io",
+                ConnectionError::ConnectionRefused(_) => "refused",
+                ConnectionError::NotConnAck(_) => "no_connack",
+                ConnectionError::RequestsDone => "requests_done",
+                // tls, websocket and proxy errors, depending on rumqttc's features
+                _ => "other",
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rumqttc::ConnectReturnCode;
+
+    use super::*;
+    use crate::PushMethod;
+
+    #[test]
+    fn invalid_push_method_is_an_error() {
+        assert!(matches!("kafka".parse::<PushMethod>(), Ok(PushMethod::Kafka)));
+        let err = "prometheus".parse::<PushMethod>().unwrap_err();
+        assert!(matches!(&err, BridgeError::InvalidPushMethod(method) if method == "prometheus"), "{}", err);
+    }
+
+    #[test]
+    fn connection_errors_are_counted_by_kind() {
+        let kind = |e: ConnectionError| poll_error_kind(&PollError::Connection(e));
+        assert_eq!(kind(ConnectionError::NetworkTimeout), "network_timeout");
+        assert_eq!(kind(ConnectionError::FlushTimeout), "flush_timeout");
+        assert_eq!(kind(ConnectionError::RequestsDone), "requests_done");
+        assert_eq!(
+            kind(ConnectionError::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset))),
+            "io"
+        );
+        assert_eq!(
+            kind(ConnectionError::ConnectionRefused(ConnectReturnCode::NotAuthorized)),
+            "refused"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::DateTime;
+use regex::Regex;
+use serde::{ Deserialize, Deserializer, Serialize, Serializer };
+
+/// Pulls the value, and optionally a timestamp, out of a structured payload before it is
+/// converted: with JSON pointers (`value = "/v"`, `timestamp = "/t"`) or a regex with `value`
+/// and `timestamp` named groups.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Extract {
+    pub value: Option<String>,
+    pub timestamp: Option<String>,
+    pub regex: Option<Pattern>,
+}
+
+/// A regex validated when the config loads.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map(Pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl Extract {
+    /// The value and timestamp (seconds) in `payload`, None if it doesn't have the expected shape.
+    pub fn apply(&self, payload: &str) -> Option<(String, Option<i64>)> {
+        if let Some(Pattern(regex)) = &self.regex {
+            let captures = regex.captures(payload)?;
+            let value = captures.name("value")?.as_str().to_string();
+            let timestamp = match captures.name("timestamp") {
+                Some(timestamp) => Some(parse_timestamp(timestamp.as_str())?),
+                None => None,
+            };
+            return Some((value, timestamp));
+        }
+
+        let json: serde_json::Value = serde_json::from_str(payload).ok()?;
+        let value = match &self.value {
+            Some(pointer) => scalar(json.pointer(pointer)?)?,
+            None => payload.to_string(),
+        };
+        let timestamp = match &self.timestamp {
+            Some(pointer) => Some(parse_timestamp(&scalar(json.pointer(pointer)?)?)?),
+            None => None,
+        };
+        Some((value, timestamp))
+    }
+}
+
+/// A JSON scalar as the string a device would have published.
+fn scalar(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Epoch seconds, epoch milliseconds (anything past the year 2286 in seconds) or RFC 3339.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    if let Ok(epoch) = s.parse::<f64>() {
+        let seconds = if epoch.abs() >= 1e10 { epoch / 1000.0 } else { epoch };
+        return Some(seconds as i64);
+    }
+    DateTime::parse_from_rfc3339(s).ok().map(|time| time.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(contents: &str) -> Extract {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn json_pointers_pick_value_and_timestamp() {
+        let extract = extract("value = '/v'\ntimestamp = '/t'");
+        assert_eq!(
+            extract.apply(r#"{"v": 21.5, "t": 1700000000000}"#),
+            Some(("21.5".to_string(), Some(1_700_000_000)))
+        );
+        assert_eq!(extract.apply(r#"{"t": 1700000000}"#), None);
+        assert_eq!(extract.apply("21.5 C"), None);
+    }
+
+    #[test]
+    fn regex_groups_pick_value_and_timestamp() {
+        let extract = extract(r"regex = '^(?P<value>[\d.]+) C at (?P<timestamp>\S+)$'");
+        assert_eq!(
+            extract.apply("21.5 C at 2023-11-14T22:13:20Z"),
+            Some(("21.5".to_string(), Some(1_700_000_000)))
+        );
+        assert_eq!(extract.apply("21.5 F"), None);
+    }
+
+    #[test]
+    fn invalid_regex_fails_to_load() {
+        assert!(toml::from_str::<Extract>("regex = '('").is_err());
+    }
+
+    #[test]
+    fn timestamps_in_seconds_millis_or_rfc_3339() {
+        assert_eq!(parse_timestamp("1700000000"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("1700000000500"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("2023-11-14T23:13:20+01:00"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
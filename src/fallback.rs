@@ -0,0 +1,67 @@
+use std::fs::{ File, OpenOptions };
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+
+use crate::point::HomiePoint;
+
+/// A line-protocol file points go to instead of the sink while it is down, for replaying
+/// into it later with `--replay`. Each point goes to one or the other, never both.
+#[derive(Debug)]
+pub struct Fallback {
+    file: File,
+    path: PathBuf,
+    // whether the last points went here rather than to the sink
+    active: bool,
+}
+
+impl Fallback {
+    pub fn open(path: &Path) -> io::Result<Fallback> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Fallback { file, path: path.to_path_buf(), active: false })
+    }
+
+    /// Append `points`, returning them with the error if the file can't be written.
+    pub fn write(&mut self, points: Vec<HomiePoint>) -> Result<(), (Vec<HomiePoint>, io::Error)> {
+        if !self.active {
+            warn!("sink down, writing points to fallback {}", self.path.display());
+            self.active = true;
+        }
+        let lines: String = points
+            .iter()
+            .map(|point| point.to_line_protocol(None) + "\n")
+            .collect();
+        self.file.write_all(lines.as_bytes()).map_err(|e| (points, e))
+    }
+
+    /// Note that the sink took points again.
+    pub fn revert(&mut self) {
+        if self.active {
+            info!("sink back, no longer writing to fallback {}", self.path.display());
+            self.active = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_line_protocol_across_reopens() {
+        let path = std::env::temp_dir().join(format!("homie-fallback-{}", std::process::id()));
+        let points = || vec![HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_000)];
+
+        let mut fallback = Fallback::open(&path).unwrap();
+        fallback.write(points()).unwrap();
+        assert!(fallback.active);
+        fallback.revert();
+        assert!(!fallback.active);
+        drop(fallback);
+        Fallback::open(&path).unwrap().write(points()).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let line = points()[0].to_line_protocol(None);
+        assert_eq!(contents, format!("{}\n{}\n", line, line));
+    }
+}
@@ -0,0 +1,64 @@
+use std::ops::RangeInclusive;
+
+use homie_controller::{ Datatype, Property };
+use serde::Serialize;
+
+/// What happens to a value outside the range its property declares in `$format`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RangeCheck {
+    /// log and count it, then write it as usual
+    Warn,
+    /// log and count it, and write nothing
+    Drop,
+}
+
+/// The declared range of an integer or float `property` if `value` is a number outside it.
+/// Values that don't parse, and properties without a range, are left to conversion.
+pub fn out_of_range(property: &Property, value: &str) -> Option<RangeInclusive<f64>> {
+    let range = match property.datatype {
+        Some(Datatype::Integer) => {
+            let range = property.range::<i64>().ok()?;
+            (*range.start() as f64)..=(*range.end() as f64)
+        }
+        _ => property.range::<f64>().ok()?,
+    };
+    let value: f64 = value.trim().parse().ok()?;
+    (!range.contains(&value)).then_some(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(datatype: Datatype, format: Option<&str>) -> Property {
+        Property {
+            id: "temperature".to_string(),
+            name: Some("Temperature".to_string()),
+            datatype: Some(datatype),
+            settable: false,
+            retained: true,
+            unit: None,
+            format: format.map(str::to_string),
+            value: None,
+        }
+    }
+
+    #[test]
+    fn values_outside_the_declared_range() {
+        let float = property(Datatype::Float, Some("-10.5:40"));
+        assert_eq!(out_of_range(&float, "41"), Some(-10.5..=40.0));
+        assert_eq!(out_of_range(&float, " -10.5 "), None);
+
+        let integer = property(Datatype::Integer, Some("0:100"));
+        assert_eq!(out_of_range(&integer, "101"), Some(0.0..=100.0));
+        assert_eq!(out_of_range(&integer, "100"), None);
+    }
+
+    #[test]
+    fn unparsed_values_and_unranged_properties_pass() {
+        assert_eq!(out_of_range(&property(Datatype::Float, Some("0:40")), "n/a"), None);
+        assert_eq!(out_of_range(&property(Datatype::Float, None), "1000"), None);
+        assert_eq!(out_of_range(&property(Datatype::String, Some("0:40")), "1000"), None);
+    }
+}
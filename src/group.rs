@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::point::{ FieldValue, HomiePoint, VALUE_FIELD };
+
+const PROPERTY_TAG: &str = "property_id_tag";
+
+#[derive(Debug)]
+struct Pending {
+    measurement: String,
+    fields: Vec<(String, FieldValue)>,
+    timestamp: i64,
+    started: Instant,
+}
+
+/// Combines the properties of a node that arrive within `window` of each other into one
+/// point with a field per property. Points are grouped on all of their tags except the
+/// property id, so e.g. raw and smoothed series stay apart.
+#[derive(Debug)]
+pub struct NodeGrouper {
+    window: Duration,
+    pending: HashMap<Vec<(String, String)>, Pending>,
+}
+
+impl NodeGrouper {
+    pub fn new(window: Duration) -> NodeGrouper {
+        NodeGrouper { window, pending: HashMap::new() }
+    }
+
+    /// Add a single-property point to its node's group; a property seen twice within the
+    /// window keeps its latest value.
+    pub fn add(&mut self, mut point: HomiePoint, now: Instant) {
+        let property_id = match point.tags.iter().position(|(key, _)| key == PROPERTY_TAG) {
+            Some(index) => point.tags.remove(index).1,
+            None => String::new(),
+        };
+
+        let group = self.pending.entry(point.tags).or_insert_with(|| Pending {
+            measurement: point.measurement,
+            fields: Vec::new(),
+            timestamp: point.timestamp,
+            started: now,
+        });
+
+        for (field, value) in point.fields {
+            let name = if field == VALUE_FIELD {
+                property_id.clone()
+            } else {
+                format!("{}_{}", property_id, field)
+            };
+            match group.fields.iter_mut().find(|(key, _)| *key == name) {
+                Some(existing) => {
+                    existing.1 = value;
+                }
+                None => group.fields.push((name, value)),
+            }
+        }
+    }
+
+    /// Remove and return the groups whose window has closed.
+    pub fn flush_due(&mut self, now: Instant) -> Vec<HomiePoint> {
+        let window = self.window;
+        let mut due = Vec::new();
+        self.pending.retain(|tags, group| {
+            if now.duration_since(group.started) < window {
+                return true;
+            }
+            due.push(HomiePoint {
+                measurement: group.measurement.clone(),
+                fields: std::mem::take(&mut group.fields),
+                tags: tags.clone(),
+                timestamp: group.timestamp,
+            });
+            false
+        });
+        due
+    }
+
+    /// Remove and return every group, window closed or not, e.g. on shutdown.
+    pub fn flush_all(&mut self, now: Instant) -> Vec<HomiePoint> {
+        // by now + window every group's window has closed
+        self.flush_due(now + self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(property_id: &str, value: f64) -> HomiePoint {
+        HomiePoint::new(value, "thermostat", "zone", property_id, 1_700_000_000)
+    }
+
+    #[test]
+    fn flushes_a_node_once_its_window_closes() {
+        let start = Instant::now();
+        let mut grouper = NodeGrouper::new(Duration::from_secs(2));
+        grouper.add(point("temperature", 21.5), start);
+        grouper.add(point("humidity", 40.0), start + Duration::from_secs(1));
+        assert!(grouper.flush_due(start + Duration::from_secs(1)).is_empty());
+
+        let due = grouper.flush_due(start + Duration::from_secs(2));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].fields, vec![
+            ("temperature".to_string(), FieldValue::Float(21.5)),
+            ("humidity".to_string(), FieldValue::Float(40.0))
+        ]);
+        assert_eq!(due[0].tag(PROPERTY_TAG), None);
+        assert_eq!(due[0].tag("node_id_tag"), Some("zone"));
+        assert!(grouper.flush_due(start + Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn a_repeated_property_keeps_its_latest_value() {
+        let start = Instant::now();
+        let mut grouper = NodeGrouper::new(Duration::from_secs(2));
+        grouper.add(point("temperature", 21.5), start);
+        grouper.add(point("temperature", 22.0), start);
+        let due = grouper.flush_all(start);
+        assert_eq!(due[0].fields, vec![("temperature".to_string(), FieldValue::Float(22.0))]);
+    }
+
+    #[test]
+    fn other_fields_are_prefixed_with_the_property() {
+        let start = Instant::now();
+        let mut grouper = NodeGrouper::new(Duration::from_secs(2));
+        let mut raw = point("mode", 4.0);
+        raw.fields.push(("raw".to_string(), FieldValue::Text("heat".to_string())));
+        grouper.add(raw, start);
+        let due = grouper.flush_all(start);
+        assert_eq!(due[0].fields[1], ("mode_raw".to_string(), FieldValue::Text("heat".to_string())));
+    }
+
+    #[test]
+    fn nodes_are_grouped_apart() {
+        let start = Instant::now();
+        let mut grouper = NodeGrouper::new(Duration::from_secs(2));
+        grouper.add(point("temperature", 21.5), start);
+        grouper.add(HomiePoint::new(19.0, "thermostat", "upstairs", "temperature", 0), start);
+        assert_eq!(grouper.flush_all(start).len(), 2);
+    }
+}
@@ -0,0 +1,325 @@
+use std::io::Write;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Duration;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{ AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE };
+use url::Url;
+
+use crate::error::BridgeError;
+use crate::point::HomiePoint;
+
+/// The endpoint points are written to.
+#[derive(Debug, Clone, Copy)]
+pub enum WriteApi<'a> {
+    /// v2's `/api/v2/write` into a bucket of `org`
+    V2 {
+        org: &'a str,
+    },
+    /// v1's `/write` into a database, optionally under a retention policy other than its default
+    V1 {
+        rp: Option<&'a str>,
+    },
+}
+
+/// Writes line protocol straight to Influx's write endpoint, bypassing influxdb_rs for
+/// control over batching, compression and timeouts.
+pub struct NativeInfluxClient {
+    http: reqwest::Client,
+    write_url: Url,
+    // the query parameter naming the bucket, or database for v1
+    bucket_key: &'static str,
+    token: String,
+    gzip: bool,
+    float_precision: Option<usize>,
+    /// points Influx accepted the request for but dropped, e.g. beyond retention
+    rejected: AtomicU64,
+}
+
+impl NativeInfluxClient {
+    /// A client writing to `bucket` through `api`, the database with v1. An empty `token`
+    /// sends no authorization, for a v1 server without auth.
+    pub fn new(
+        base_url: &str,
+        api: WriteApi,
+        bucket: &str,
+        token: &str,
+        timeout: Duration,
+        gzip: bool,
+        float_precision: Option<usize>
+    ) -> Result<NativeInfluxClient, BridgeError> {
+        let path = match api {
+            WriteApi::V2 { .. } => "/api/v2/write",
+            WriteApi::V1 { .. } => "/write",
+        };
+        let mut write_url = Url::parse(base_url)
+            .and_then(|url| url.join(path))
+            .map_err(|e| BridgeError::InvalidUrl(base_url.to_string(), e))?;
+        let bucket_key = match api {
+            WriteApi::V2 { org } => {
+                write_url.query_pairs_mut().append_pair("org", org).append_pair("bucket", bucket);
+                "bucket"
+            }
+            WriteApi::V1 { rp } => {
+                write_url.query_pairs_mut().append_pair("db", bucket);
+                if let Some(rp) = rp {
+                    write_url.query_pairs_mut().append_pair("rp", rp);
+                }
+                "db"
+            }
+        };
+        write_url.query_pairs_mut().append_pair("precision", "s");
+
+        let http = reqwest::Client
+            ::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(BridgeError::HttpClient)?;
+
+        Ok(NativeInfluxClient {
+            http,
+            write_url,
+            bucket_key,
+            token: token.to_string(),
+            gzip,
+            float_precision,
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    pub fn write_url(&self) -> &Url {
+        &self.write_url
+    }
+
+    /// Points dropped by partial writes so far.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// The write URL with `bucket` in place of the configured one.
+    pub fn bucket_url(&self, bucket: &str) -> Url {
+        let mut url = self.write_url.clone();
+        let pairs: Vec<(String, String)> = self.write_url
+            .query_pairs()
+            .map(|(key, value)| {
+                let value = if key == self.bucket_key {
+                    bucket.to_string()
+                } else {
+                    value.into_owned()
+                };
+                (key.into_owned(), value)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        url
+    }
+
+    /// Build the write request for `points`, one line-protocol line each, to `bucket` or the
+    /// configured bucket.
+    pub fn request(
+        &self,
+        points: &[HomiePoint],
+        bucket: Option<&str>
+    ) -> Result<reqwest::Request, reqwest::Error> {
+        let body = points
+            .iter()
+            .map(|point| point.to_line_protocol(self.float_precision))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut request = self.http
+            .post(bucket.map_or_else(|| self.write_url.clone(), |bucket| self.bucket_url(bucket)))
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8");
+        if !self.token.is_empty() {
+            request = request.header(AUTHORIZATION, format!("Token {}", self.token));
+        }
+
+        request = if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            // writing into a Vec can't fail
+            encoder.write_all(body.as_bytes()).unwrap();
+            request.header(CONTENT_ENCODING, "gzip").body(encoder.finish().unwrap())
+        } else {
+            request.body(body)
+        };
+
+        request.build()
+    }
+
+    /// Split `points` into runs whose line protocol fits in `max_bytes`, one run without a
+    /// limit. A point larger than the limit goes on its own and is left to the server to reject.
+    pub fn chunks<'a>(
+        &self,
+        points: &'a [HomiePoint],
+        max_bytes: Option<usize>
+    ) -> Vec<&'a [HomiePoint]> {
+        if points.is_empty() {
+            return Vec::new();
+        }
+        let Some(max_bytes) = max_bytes else {
+            return vec![points];
+        };
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut size = 0;
+        for (i, point) in points.iter().enumerate() {
+            // each line plus its newline separator
+            let line = point.to_line_protocol(self.float_precision).len() + 1;
+            if i > start && size + line > max_bytes {
+                chunks.push(&points[start..i]);
+                start = i;
+                size = 0;
+            }
+            size += line;
+        }
+        chunks.push(&points[start..]);
+        chunks
+    }
+
+    pub async fn write(
+        &self,
+        points: &[HomiePoint],
+        bucket: Option<&str>
+    ) -> Result<(), reqwest::Error> {
+        let request = self.request(points, bucket)?;
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        let error = response.error_for_status_ref().err();
+        let body = response.text().await.unwrap_or_default();
+        let message = error_message(&body);
+
+        let Some(error) = error else {
+            if !message.is_empty() {
+                warn!("influxdb: write accepted with a message: {}", message);
+            }
+            return Ok(());
+        };
+        // the rest of a partial write landed, and the dropped points would be dropped again
+        if let Some(dropped) = partial_write_dropped(&message) {
+            warn!("influxdb: partial write, {} point(s) dropped: {}", dropped, message);
+            self.rejected.fetch_add(dropped, Ordering::Relaxed);
+            return Ok(());
+        }
+        warn!("influxdb: write rejected ({}): {}", status, message);
+        Err(error)
+    }
+}
+
+/// The `message` of an Influx error body, or the body as it is.
+fn error_message(body: &str) -> String {
+    serde_json
+        ::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// The number of points a partial write dropped, from e.g. `partial write: points beyond
+/// retention policy dropped=3`.
+fn partial_write_dropped(message: &str) -> Option<u64> {
+    if !message.contains("partial write") {
+        return None;
+    }
+    let (_, count) = message.rsplit_once("dropped=")?;
+    count
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn client(api: WriteApi, token: &str, gzip: bool) -> NativeInfluxClient {
+        NativeInfluxClient::new("http://influx:8086", api, "homie", token, Duration::from_secs(1), gzip, None)
+            .unwrap()
+    }
+
+    fn points(n: usize) -> Vec<HomiePoint> {
+        (0..n).map(|i| HomiePoint::new(i as f64, "thermostat", "zone", "temperature", 0)).collect()
+    }
+
+    #[test]
+    fn builds_v2_and_v1_write_urls() {
+        let v2 = client(WriteApi::V2 { org: "home" }, "t", false);
+        let url = "http://influx:8086/api/v2/write?org=home&bucket=homie&precision=s";
+        assert_eq!(v2.write_url().as_str(), url);
+        let v1 = client(WriteApi::V1 { rp: Some("weekly") }, "", false);
+        let url = "http://influx:8086/write?db=homie&rp=weekly&precision=s";
+        assert_eq!(v1.write_url().as_str(), url);
+        let url = "http://influx:8086/write?db=other&rp=weekly&precision=s";
+        assert_eq!(v1.bucket_url("other").as_str(), url);
+    }
+
+    #[test]
+    fn rejects_an_invalid_url() {
+        let client = NativeInfluxClient::new(
+            "not a url",
+            WriteApi::V1 { rp: None },
+            "homie",
+            "",
+            Duration::from_secs(1),
+            false,
+            None
+        );
+        assert!(matches!(client, Err(BridgeError::InvalidUrl(..))));
+    }
+
+    #[test]
+    fn request_carries_the_token_and_gzipped_lines() {
+        let client = client(WriteApi::V2 { org: "home" }, "secret", true);
+        let points = points(2);
+        let request = client.request(&points, Some("archive")).unwrap();
+        assert!(request.url().as_str().contains("bucket=archive"));
+        assert_eq!(request.headers()[AUTHORIZATION], "Token secret");
+        assert_eq!(request.headers()[CONTENT_ENCODING], "gzip");
+
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+        let mut lines = String::new();
+        GzDecoder::new(body).read_to_string(&mut lines).unwrap();
+        let expected: Vec<String> = points.iter().map(|point| point.to_line_protocol(None)).collect();
+        assert_eq!(lines, expected.join("\n"));
+    }
+
+    #[test]
+    fn no_token_sends_no_authorization() {
+        let client = client(WriteApi::V1 { rp: None }, "", false);
+        let request = client.request(&points(1), None).unwrap();
+        assert!(request.headers().get(AUTHORIZATION).is_none());
+    }
+
+    #[test]
+    fn chunks_fit_the_byte_limit() {
+        let client = client(WriteApi::V1 { rp: None }, "", false);
+        let points = points(5);
+        let line = points[0].to_line_protocol(None).len() + 1;
+        assert_eq!(client.chunks(&points, None).len(), 1);
+        let sizes: Vec<usize> = client
+            .chunks(&points, Some(line * 2))
+            .iter()
+            .map(|chunk| chunk.len())
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        // a point over the limit still goes, on its own
+        assert_eq!(client.chunks(&points[..2], Some(1)).len(), 2);
+        assert!(client.chunks(&[], Some(1)).is_empty());
+    }
+
+    #[test]
+    fn reads_error_bodies() {
+        assert_eq!(error_message(r#"{"code":"invalid","message":"bad line"}"#), "bad line");
+        assert_eq!(error_message(" plain text\n"), "plain text");
+        let partial = "partial write: points beyond retention policy dropped=3";
+        assert_eq!(partial_write_dropped(partial), Some(3));
+        assert_eq!(partial_write_dropped("unauthorized"), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use reqwest::header::{ AUTHORIZATION, CONTENT_TYPE };
+use reqwest::{ Method, StatusCode };
+use serde_json::{ json, Value };
+
+use crate::error::BridgeError;
+
+/// Creates the org and buckets the bridge writes to through the Influx v2 API, for a first
+/// start against a fresh server.
+pub struct InfluxSetup {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl InfluxSetup {
+    pub fn new(base_url: &str, token: &str, timeout: Duration) -> Result<InfluxSetup, BridgeError> {
+        let http = reqwest::Client
+            ::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(BridgeError::HttpClient)?;
+        Ok(InfluxSetup {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    /// Make sure each of `buckets` exists in `org`, creating the missing ones, and the org as
+    /// well if `create_org`. Returns the names of what was created.
+    pub async fn ensure(
+        &self,
+        org: &str,
+        buckets: &[&str],
+        create_org: bool
+    ) -> Result<Vec<String>, BridgeError> {
+        let mut created = Vec::new();
+
+        let orgs = self.call(Method::GET, "/api/v2/orgs", &[("org", org)], None).await?;
+        let org_id = match orgs.and_then(|orgs| first_id(&orgs["orgs"])) {
+            Some(id) => id,
+            None if create_org => {
+                let body = json!({ "name": org });
+                let new_org = self.call(Method::POST, "/api/v2/orgs", &[], Some(body)).await?;
+                created.push(format!("org {}", org));
+                new_org
+                    .and_then(|new_org| new_org["id"].as_str().map(str::to_string))
+                    .ok_or_else(|| setup_error(format!("creating org {} returned no id", org)))?
+            }
+            None => {
+                return Err(
+                    setup_error(format!("org {} doesn't exist, pass --influx-create-org to create it", org))
+                );
+            }
+        };
+
+        for bucket in buckets {
+            let query = [("name", *bucket), ("orgID", org_id.as_str())];
+            let existing = self.call(Method::GET, "/api/v2/buckets", &query, None).await?;
+            if existing.and_then(|existing| first_id(&existing["buckets"])).is_some() {
+                debug!("influx bucket {} exists", bucket);
+                continue;
+            }
+            let body = json!({ "orgID": org_id, "name": bucket, "retentionRules": [] });
+            self.call(Method::POST, "/api/v2/buckets", &[], Some(body)).await?;
+            created.push(format!("bucket {}", bucket));
+        }
+        Ok(created)
+    }
+
+    /// Send one API request, returning its JSON body or `None` for a 404.
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<Value>
+    ) -> Result<Option<Value>, BridgeError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self.http
+            .request(method.clone(), &url)
+            .query(query)
+            .header(AUTHORIZATION, format!("Token {}", self.token));
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(body.to_string());
+        }
+
+        let response = request
+            .send().await
+            .map_err(|e| BridgeError::Connect(url.clone(), e.to_string()))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN =>
+                Err(
+                    setup_error(
+                        format!("{} {} was refused ({}), the token lacks permission: {}", method, path, status, text)
+                    )
+                ),
+            status if !status.is_success() =>
+                Err(setup_error(format!("{} {} failed ({}): {}", method, path, status, text))),
+            _ =>
+                serde_json
+                    ::from_str(&text)
+                    .map(Some)
+                    .map_err(|e| setup_error(format!("{} {} returned invalid JSON: {}", method, path, e))),
+        }
+    }
+}
+
+fn first_id(list: &Value) -> Option<String> {
+    list.as_array()?.first()?["id"].as_str().map(str::to_string)
+}
+
+fn setup_error(message: String) -> BridgeError {
+    BridgeError::InfluxSetup(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::TcpListener;
+    use std::thread::{ self, JoinHandle };
+
+    use super::*;
+
+    /// A server answering one request per connection with the given status lines and bodies,
+    /// in order, handing back the request lines it saw.
+    fn serve(responses: Vec<(&'static str, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(request_line.trim_end().to_string());
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn creates_missing_buckets_in_an_existing_org() {
+        let (url, server) = serve(
+            vec![
+                ("200 OK", r#"{"orgs": [{"id": "o1"}]}"#),
+                ("200 OK", r#"{"buckets": [{"id": "b1"}]}"#),
+                ("404 Not Found", r#"{"code": "not found"}"#),
+                ("201 Created", r#"{"id": "b2"}"#)
+            ]
+        );
+        let setup = InfluxSetup::new(&url, "token", Duration::from_secs(5)).unwrap();
+        let created = setup.ensure("home", &["homie", "annotations"], false).await.unwrap();
+        assert_eq!(created, ["bucket annotations"]);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /api/v2/orgs?org=home "), "{}", requests[0]);
+        assert!(requests[2].starts_with("GET /api/v2/buckets?name=annotations&orgID=o1 "), "{}", requests[2]);
+        assert!(requests[3].starts_with("POST /api/v2/buckets "), "{}", requests[3]);
+    }
+
+    #[tokio::test]
+    async fn missing_org_is_only_created_when_asked() {
+        let (url, server) = serve(vec![("200 OK", r#"{"orgs": []}"#)]);
+        let setup = InfluxSetup::new(&url, "token", Duration::from_secs(5)).unwrap();
+        let err = setup.ensure("home", &["homie"], false).await.unwrap_err();
+        assert!(err.to_string().contains("pass --influx-create-org"), "{}", err);
+        server.join().unwrap();
+
+        let (url, server) = serve(
+            vec![
+                ("200 OK", r#"{"orgs": []}"#),
+                ("201 Created", r#"{"id": "o1"}"#),
+                ("404 Not Found", "{}"),
+                ("201 Created", r#"{"id": "b1"}"#)
+            ]
+        );
+        let setup = InfluxSetup::new(&url, "token", Duration::from_secs(5)).unwrap();
+        let created = setup.ensure("home", &["homie"], true).await.unwrap();
+        assert_eq!(created, ["org home", "bucket homie"]);
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn refused_token_is_reported() {
+        let (url, server) = serve(vec![("401 Unauthorized", r#"{"code": "unauthorized"}"#)]);
+        let setup = InfluxSetup::new(&url, "token", Duration::from_secs(5)).unwrap();
+        let err = setup.ensure("home", &["homie"], false).await.unwrap_err();
+        assert!(err.to_string().contains("the token lacks permission"), "{}", err);
+        server.join().unwrap();
+    }
+}
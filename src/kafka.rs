@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+
+use crate::error::BridgeError;
+use crate::point::HomiePoint;
+
+/// How each point is serialized into a Kafka message.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum KafkaFormat {
+    /// telegraf's JSON metric shape
+    Json,
+    /// one line of influx line protocol
+    Line,
+}
+
+/// Sends one keyed message to a topic: rdkafka's producer, built with the `kafka` feature.
+pub trait Producer: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        topic: &'a str,
+        key: &'a str,
+        payload: &'a str
+    ) -> BoxFuture<'a, Result<(), String>>;
+}
+
+#[cfg(feature = "kafka")]
+mod rdkafka_producer {
+    use std::time::Duration;
+
+    use futures::future::{ BoxFuture, FutureExt };
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{ FutureProducer, FutureRecord };
+
+    use super::Producer;
+    use crate::error::BridgeError;
+
+    impl Producer for FutureProducer {
+        fn send<'a>(
+            &'a self,
+            topic: &'a str,
+            key: &'a str,
+            payload: &'a str
+        ) -> BoxFuture<'a, Result<(), String>> {
+            let record = FutureRecord::to(topic).key(key).payload(payload);
+            // the message timeout bounds delivery, so don't also wait for queue space
+            FutureProducer::send(self, record, Duration::ZERO)
+                .map(|res| res.map(|_| ()).map_err(|(e, _message)| e.to_string()))
+                .boxed()
+        }
+    }
+
+    pub fn producer(brokers: &str, timeout: Duration) -> Result<Box<dyn Producer>, BridgeError> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", timeout.as_millis().to_string())
+            .create()
+            .map_err(|e| BridgeError::Connect(brokers.to_string(), e.to_string()))?;
+        Ok(Box::new(producer))
+    }
+}
+
+#[cfg(feature = "kafka")]
+use rdkafka_producer::producer;
+
+#[cfg(not(feature = "kafka"))]
+fn producer(_brokers: &str, _timeout: Duration) -> Result<Box<dyn Producer>, BridgeError> {
+    Err(BridgeError::NotBuilt("kafka"))
+}
+
+/// Produces one message per point to a topic, keyed by device id so a device's values stay
+/// in order on one partition.
+pub struct KafkaClient {
+    producer: Box<dyn Producer>,
+    topic: String,
+    format: KafkaFormat,
+    float_precision: Option<usize>,
+}
+
+impl KafkaClient {
+    pub fn new(
+        brokers: &str,
+        topic: &str,
+        format: KafkaFormat,
+        timeout: Duration,
+        float_precision: Option<usize>
+    ) -> Result<KafkaClient, BridgeError> {
+        let producer = producer(brokers, timeout)?;
+        Ok(KafkaClient::with_producer(producer, topic, format, float_precision))
+    }
+
+    pub fn with_producer(
+        producer: Box<dyn Producer>,
+        topic: &str,
+        format: KafkaFormat,
+        float_precision: Option<usize>
+    ) -> KafkaClient {
+        KafkaClient { producer, topic: topic.to_string(), format, float_precision }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub async fn write(&self, point: &HomiePoint) -> Result<(), String> {
+        let payload = match self.format {
+            KafkaFormat::Json => point.to_telegraf_json(self.float_precision).to_string(),
+            KafkaFormat::Line => point.to_line_protocol(self.float_precision),
+        };
+        let key = point.tag("device_id_tag").unwrap_or_default();
+        self.producer.send(&self.topic, key, &payload).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+
+    use futures::future::{ self, FutureExt };
+
+    use super::*;
+
+    /// Keeps the messages it is given, as (topic, key, payload).
+    #[derive(Default, Clone)]
+    struct MockProducer {
+        sent: Arc<Mutex<Vec<(String, String, String)>>>,
+    }
+
+    impl Producer for MockProducer {
+        fn send<'a>(
+            &'a self,
+            topic: &'a str,
+            key: &'a str,
+            payload: &'a str
+        ) -> BoxFuture<'a, Result<(), String>> {
+            let message = (topic.to_string(), key.to_string(), payload.to_string());
+            self.sent.lock().unwrap().push(message);
+            future::ready(Ok(())).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn one_point_is_one_message_keyed_by_device() {
+        let producer = MockProducer::default();
+        let client = KafkaClient::with_producer(
+            Box::new(producer.clone()),
+            "homie",
+            KafkaFormat::Line,
+            None
+        );
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        client.write(&point).await.unwrap();
+
+        let sent = producer.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        let (topic, key, payload) = &sent[0];
+        assert_eq!(topic, "homie");
+        assert_eq!(key, "thermostat");
+        assert_eq!(payload, &point.to_line_protocol(None));
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    #[test]
+    fn without_the_feature_there_is_no_producer() {
+        let client = KafkaClient::new("localhost:9092", "homie", KafkaFormat::Json, Duration::ZERO, None);
+        assert!(matches!(client, Err(BridgeError::NotBuilt("kafka"))));
+    }
+}
@@ -0,0 +1,7 @@
+//! Conversion of Homie property values into numbers, as the bridge does it, for programs
+//! embedding it with converters of their own.
+
+#[macro_use]
+extern crate log;
+
+pub mod convert;
@@ -0,0 +1,103 @@
+use std::collections::{ BTreeMap, HashMap };
+
+use homie_controller::{ Device, Property };
+
+/// Remembers the metadata devices and properties declared, warning when a declared attribute
+/// changes mid-run, e.g. a device republishing a property with another `$unit`. The latest
+/// declaration always wins, as it does in the controller; the warning explains the tags or
+/// conversions that change with it. An attribute published for the first time isn't a change.
+#[derive(Debug, Default)]
+pub struct MetadataWatch {
+    seen: HashMap<String, BTreeMap<&'static str, String>>,
+}
+
+impl MetadataWatch {
+    pub fn new() -> MetadataWatch {
+        MetadataWatch::default()
+    }
+
+    /// Check the device attributes device tags are derived from; `label` names it in logs.
+    pub fn device(&mut self, label: &str, device: &Device) -> usize {
+        self.check(label, [
+            ("$name", device.name.clone()),
+            ("$implementation", device.implementation.clone()),
+            ("$fw/name", device.firmware_name.clone()),
+        ])
+    }
+
+    /// Check a property's attributes; `label` names it in logs.
+    pub fn property(&mut self, label: &str, property: &Property) -> usize {
+        self.check(label, [
+            ("$name", property.name.clone()),
+            ("$datatype", property.datatype.map(|datatype| datatype.to_string())),
+            ("$unit", property.unit.clone()),
+            ("$format", property.format.clone()),
+        ])
+    }
+
+    /// Record the attributes, returning how many changed.
+    fn check<const N: usize>(
+        &mut self,
+        label: &str,
+        attributes: [(&'static str, Option<String>); N]
+    ) -> usize {
+        let seen = self.seen.entry(label.to_string()).or_default();
+        let mut changed = 0;
+        for (attribute, value) in attributes {
+            let Some(value) = value else {
+                continue;
+            };
+            match seen.insert(attribute, value.clone()) {
+                Some(previous) if previous != value => {
+                    warn!(
+                        "{} changed its {} from {:?} to {:?}, using the latest",
+                        label,
+                        attribute,
+                        previous,
+                        value
+                    );
+                    changed += 1;
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use homie_controller::Datatype;
+
+    use super::*;
+
+    fn property(unit: Option<&str>) -> Property {
+        Property {
+            id: "temperature".to_string(),
+            name: Some("Temperature".to_string()),
+            datatype: Some(Datatype::Float),
+            settable: false,
+            retained: true,
+            unit: unit.map(str::to_string),
+            format: None,
+            value: None,
+        }
+    }
+
+    #[test]
+    fn changed_attributes_are_counted() {
+        let mut watch = MetadataWatch::new();
+        assert_eq!(watch.property("thermostat/zone/temperature", &property(None)), 0);
+        // first published, not a change
+        assert_eq!(watch.property("thermostat/zone/temperature", &property(Some("°C"))), 0);
+        assert_eq!(watch.property("thermostat/zone/temperature", &property(Some("°C"))), 0);
+        assert_eq!(watch.property("thermostat/zone/temperature", &property(Some("°F"))), 1);
+    }
+
+    #[test]
+    fn labels_are_watched_apart() {
+        let mut watch = MetadataWatch::new();
+        watch.property("thermostat/zone/temperature", &property(Some("°C")));
+        assert_eq!(watch.property("heater/zone/temperature", &property(Some("°F"))), 0);
+    }
+}
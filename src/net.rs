@@ -0,0 +1,125 @@
+use std::io;
+use std::net::{ IpAddr, Ipv6Addr };
+
+use serde::Serialize;
+
+/// Address family preferred when resolving endpoint hostnames.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpVersion {
+    /// use the host as given and let each client resolve it
+    Any,
+    V4,
+    V6,
+}
+
+/// This machine's hostname, or `unknown`.
+pub fn hostname() -> String {
+    std::fs
+        ::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Strip the brackets from a bracketed IPv6 literal such as `[::1]`.
+pub fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Split a `host`, `host:port` or `[v6]:port` entry, taking `default_port` when it has none.
+/// A bare IPv6 literal is taken as a host. `None` if the port isn't a number.
+pub fn split_host_port(entry: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = match entry.rsplit_once(':') {
+        Some((host, port)) if host.starts_with('[') && host.ends_with(']') => (host, Some(port)),
+        Some((host, port)) if !host.contains(':') => (host, Some(port)),
+        _ => (entry, None),
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    Some((unbracket(host).to_string(), port))
+}
+
+/// `host:port` for a URL or connection string, bracketing IPv6 literals (`[::1]:8086`).
+pub fn host_port(host: &str, port: u16) -> String {
+    let host = unbracket(host);
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Resolve `host` to an address of the preferred family, returned unbracketed. With
+/// `IpVersion::Any` the host is returned as given.
+pub async fn resolve(host: &str, port: u16, version: IpVersion) -> io::Result<String> {
+    let host = unbracket(host);
+    if version == IpVersion::Any {
+        return Ok(host.to_string());
+    }
+
+    tokio::net
+        ::lookup_host((host, port)).await?
+        .map(|addr| addr.ip())
+        .find(|ip| matches!((version, ip), (IpVersion::V4, IpAddr::V4(_)) | (IpVersion::V6, IpAddr::V6(_))))
+        .map(|ip| ip.to_string())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no {:?} address", host, version)
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(entry: &str) -> Option<(String, u16)> {
+        split_host_port(entry, 8094)
+    }
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(split("telegraf"), Some(("telegraf".to_string(), 8094)));
+        assert_eq!(split("telegraf:5094"), Some(("telegraf".to_string(), 5094)));
+        assert_eq!(split("10.0.0.2:5094"), Some(("10.0.0.2".to_string(), 5094)));
+        assert_eq!(split("telegraf:port"), None);
+        assert_eq!(split("telegraf:70000"), None);
+    }
+
+    #[test]
+    fn splits_ipv6_literals() {
+        assert_eq!(split("[::1]:5094"), Some(("::1".to_string(), 5094)));
+        assert_eq!(split("[fe80::1]"), Some(("fe80::1".to_string(), 8094)));
+        // a bare literal's last group isn't a port
+        assert_eq!(split("fe80::1"), Some(("fe80::1".to_string(), 8094)));
+    }
+
+    #[test]
+    fn brackets_ipv6_in_host_port() {
+        assert_eq!(host_port("influx", 8086), "influx:8086");
+        assert_eq!(host_port("::1", 8086), "[::1]:8086");
+        assert_eq!(host_port("[::1]", 8086), "[::1]:8086");
+        assert_eq!(unbracket("[::1]"), "::1");
+    }
+
+    #[tokio::test]
+    async fn resolves_to_the_preferred_family() {
+        assert_eq!(resolve("[::1]", 80, IpVersion::Any).await.unwrap(), "::1");
+        assert_eq!(resolve("127.0.0.1", 80, IpVersion::V4).await.unwrap(), "127.0.0.1");
+        assert!(resolve("127.0.0.1", 80, IpVersion::V6).await.is_err());
+    }
+
+    #[test]
+    fn hostname_is_never_empty() {
+        assert!(!hostname().is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use tokio::time::Instant;
+
+// how long a final notification may hold up exiting
+const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct ComponentState {
+    up: bool,
+    detail: String,
+    /// state last sent, if any
+    sent: Option<bool>,
+    sent_at: Option<Instant>,
+}
+
+/// POSTs a JSON notification to a webhook when a connection (`mqtt`, `sink`) goes down or
+/// comes back up. A component notifies at most once per `min_interval`; if it flaps in
+/// between, only the state it settles on is sent once the interval is over.
+pub struct Notifier {
+    http: reqwest::Client,
+    url: String,
+    min_interval: Duration,
+    components: BTreeMap<&'static str, ComponentState>,
+}
+
+impl Notifier {
+    pub fn new(url: &str, min_interval: Duration) -> Notifier {
+        Notifier {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+            min_interval,
+            components: BTreeMap::new(),
+        }
+    }
+
+    /// Record the state of `component`; the notification goes out with the next `flush`.
+    pub fn set(&mut self, component: &'static str, up: bool, detail: &str) {
+        let state = self.components.entry(component).or_insert_with(|| ComponentState {
+            up,
+            detail: String::new(),
+            sent: None,
+            sent_at: None,
+        });
+        if state.up != up || state.sent.is_none() {
+            state.detail = detail.to_string();
+        }
+        state.up = up;
+    }
+
+    /// Send the notifications due, without waiting for them to be delivered.
+    pub fn flush(&mut self, now: Instant) {
+        let mut due = Vec::new();
+        for (component, state) in &mut self.components {
+            let changed = state.sent.map_or(!state.up, |sent| sent != state.up);
+            let limited = state.sent_at.is_some_and(|at| now.duration_since(at) < self.min_interval);
+            if !changed || limited {
+                continue;
+            }
+            state.sent = Some(state.up);
+            state.sent_at = Some(now);
+            due.push((*component, state.up, state.detail.clone()));
+        }
+        for (component, up, detail) in due {
+            let request = self.request(component, up, &detail);
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                    warn!("notify webhook failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Send a notification now and wait for it, e.g. just before exiting.
+    pub async fn send_now(&mut self, component: &'static str, up: bool, detail: &str) {
+        self.set(component, up, detail);
+        let request = self.request(component, up, detail).timeout(EXIT_TIMEOUT);
+        if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+            warn!("notify webhook failed: {}", e);
+        }
+    }
+
+    fn request(&self, component: &str, up: bool, detail: &str) -> reqwest::RequestBuilder {
+        let state = if up { "up" } else { "down" };
+        let mut text = format!("homie-input: {} {}", component, state);
+        if !detail.is_empty() {
+            text.push_str(&format!(": {}", detail));
+        }
+        info!("notify: {}", text);
+        let payload =
+            json!({
+            "component": component,
+            "state": state,
+            "detail": detail,
+            "text": text,
+            "timestamp": Utc::now().to_rfc3339(),
+        });
+        self.http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+    // nothing listens there, the spawned sends just fail
+    fn notifier() -> Notifier {
+        Notifier::new("http://127.0.0.1:9/notify", MIN_INTERVAL)
+    }
+
+    fn sent(notifier: &Notifier, component: &str) -> Option<bool> {
+        notifier.components[component].sent
+    }
+
+    #[tokio::test]
+    async fn first_up_is_not_notified_but_down_is() {
+        let now = Instant::now();
+        let mut notifier = notifier();
+        notifier.set("mqtt", true, "connected");
+        notifier.flush(now);
+        assert_eq!(sent(&notifier, "mqtt"), None);
+
+        notifier.set("mqtt", false, "connection refused");
+        notifier.flush(now);
+        assert_eq!(sent(&notifier, "mqtt"), Some(false));
+    }
+
+    #[tokio::test]
+    async fn flapping_sends_the_settled_state_after_the_interval() {
+        let start = Instant::now();
+        let mut notifier = notifier();
+        notifier.set("sink", false, "write failed");
+        notifier.flush(start);
+
+        notifier.set("sink", true, "");
+        notifier.flush(start + Duration::from_secs(10));
+        assert_eq!(sent(&notifier, "sink"), Some(false));
+
+        notifier.set("sink", false, "write failed");
+        notifier.set("sink", true, "");
+        notifier.flush(start + MIN_INTERVAL);
+        assert_eq!(sent(&notifier, "sink"), Some(true));
+    }
+
+    #[test]
+    fn payload_describes_the_change() {
+        let request = notifier().request("sink", false, "write failed").build().unwrap();
+        let body: serde_json::Value = serde_json
+            ::from_slice(request.body().unwrap().as_bytes().unwrap())
+            .unwrap();
+        assert_eq!(body["component"], "sink");
+        assert_eq!(body["state"], "down");
+        assert_eq!(body["text"], "homie-input: sink down: write failed");
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use tokio::time::Instant;
+
+use crate::batch::Batcher;
+use crate::group::NodeGrouper;
+use crate::point::HomiePoint;
+use crate::sanitize::Sanitize;
+use crate::schema::Schema;
+use crate::sequence::Sequencer;
+
+pub const INSTANCE_TAG: &str = "instance_id_tag";
+
+/// The last steps a point takes on its way to the sink: the schema and sanitizing, the
+/// max age, the bridge's instance tag and sequence numbers.
+pub struct Finisher {
+    schema: Schema,
+    sanitize: Sanitize,
+    max_point_age: Option<u64>,
+    instance_id: Option<String>,
+    sequencer: Option<Sequencer>,
+    stale_points: u64,
+    instance_collisions: u64,
+}
+
+impl Finisher {
+    pub fn new(
+        schema: Schema,
+        sanitize: Sanitize,
+        max_point_age: Option<u64>,
+        instance_id: Option<String>,
+        sequencer: Option<Sequencer>
+    ) -> Finisher {
+        Finisher {
+            schema,
+            sanitize,
+            max_point_age,
+            instance_id,
+            sequencer,
+            stale_points: 0,
+            instance_collisions: 0,
+        }
+    }
+
+    /// The point as it is written at `now`, in epoch seconds, or `None` if it is too old.
+    pub fn finish(&mut self, point: HomiePoint, now: i64) -> Option<HomiePoint> {
+        let mut point = self.sanitize.apply(self.schema.apply(point));
+
+        if let Some(max_age) = self.max_point_age {
+            let age = now - point.timestamp;
+            if age > (max_age as i64) {
+                self.stale_points += 1;
+                warn!(
+                    "dropping point {}s old, older than max age {}s ({} dropped): {:?}",
+                    age,
+                    max_age,
+                    self.stale_points,
+                    &point
+                );
+                return None;
+            }
+        }
+
+        if let Some(id) = &self.instance_id {
+            // a device tag of the same name wins, the bridge's id is only added
+            if point.tag(INSTANCE_TAG).is_some() {
+                if self.instance_collisions == 0 {
+                    warn!("{} already set on {:?}, not overriding it", INSTANCE_TAG, point);
+                }
+                self.instance_collisions += 1;
+            } else {
+                point.tags.push((INSTANCE_TAG.to_string(), id.clone()));
+            }
+        }
+        if let Some(sequencer) = self.sequencer.as_mut() {
+            sequencer.apply(&mut point);
+        }
+        Some(point)
+    }
+}
+
+/// The points the main loop holds between polls: those held back while the sink is paused
+/// or down, node groups whose window is still open and the batcher's.
+pub struct Outbox {
+    pub paused: VecDeque<HomiePoint>,
+    pub grouper: Option<NodeGrouper>,
+    pub batcher: Batcher,
+    pub finisher: Finisher,
+}
+
+impl Outbox {
+    /// Everything still held, in the order it arrived, finished for the sink as of `now`
+    /// and `wall_now` in epoch seconds; for shutdown.
+    pub fn drain(&mut self, now: Instant, wall_now: i64) -> Vec<HomiePoint> {
+        // the batcher's points were finished on their way in, while paused nothing reaches it
+        let mut points = self.batcher.take();
+        let mut held: Vec<HomiePoint> = self.paused.drain(..).collect();
+        if let Some(grouper) = self.grouper.as_mut() {
+            held.extend(grouper.flush_all(now));
+        }
+        points.extend(held.into_iter().filter_map(|point| self.finisher.finish(point, wall_now)));
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::point::FieldValue;
+
+    fn finisher() -> Finisher {
+        Finisher::new(Schema::Flat, Sanitize::Escape, Some(60), Some("bridge-1".to_string()), None)
+    }
+
+    fn point(property_id: &str, value: f64, timestamp: i64) -> HomiePoint {
+        HomiePoint::new(value, "thermostat", "zone", property_id, timestamp)
+    }
+
+    #[test]
+    fn finish_drops_stale_points() {
+        let mut finisher = finisher();
+        assert!(finisher.finish(point("temperature", 21.5, 1_000), 1_060).is_some());
+        assert!(finisher.finish(point("temperature", 21.5, 1_000), 1_061).is_none());
+    }
+
+    #[test]
+    fn finish_tags_the_instance_unless_already_tagged() {
+        let mut finisher = finisher();
+        let tagged = finisher.finish(point("temperature", 21.5, 1_000), 1_000).unwrap();
+        assert_eq!(tagged.tag(INSTANCE_TAG), Some("bridge-1"));
+
+        let mut own = point("temperature", 21.5, 1_000);
+        own.tags.push((INSTANCE_TAG.to_string(), "device".to_string()));
+        let kept = finisher.finish(own, 1_000).unwrap();
+        assert_eq!(kept.tag(INSTANCE_TAG), Some("device"));
+    }
+
+    #[test]
+    fn drain_returns_everything_held() {
+        let now = Instant::now();
+        let mut outbox = Outbox {
+            paused: VecDeque::from([point("humidity", 40.0, 1_000)]),
+            grouper: Some(NodeGrouper::new(Duration::from_secs(2))),
+            batcher: Batcher::new(100, Duration::from_secs(60), now),
+            finisher: finisher(),
+        };
+        outbox.batcher.push(vec![point("setpoint", 20.0, 990)]);
+        outbox.grouper.as_mut().unwrap().add(point("temperature", 21.5, 1_000), now);
+
+        let points = outbox.drain(now, 1_000);
+        assert_eq!(points.len(), 3);
+        // the batcher's points were finished already, the rest on the way out
+        assert_eq!(points[0].tag(INSTANCE_TAG), None);
+        assert_eq!(points[1].tag(INSTANCE_TAG), Some("bridge-1"));
+        assert_eq!(points[2].fields[0], ("temperature".to_string(), FieldValue::Float(21.5)));
+
+        assert!(outbox.drain(now, 1_000).is_empty());
+    }
+}
@@ -0,0 +1,708 @@
+use std::sync::Arc;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::cache::{ series_cache, SeriesCache };
+use crate::config::{ self, Config };
+use crate::control::Control;
+use crate::convert::{ self, Converter, Invalid, Outcome };
+use crate::device_clock::DeviceClocks;
+use crate::point::{ FieldValue, HomiePoint, MetricType };
+use crate::rate::RateTracker;
+use crate::sampling::Sampler;
+use crate::setpoint;
+use crate::smoothing::Smoother;
+use crate::unknown_mode::UnknownModes;
+
+/// What an empty value, published to clear a property, is written as.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyValue {
+    /// write nothing
+    Skip,
+    /// write 0.0, like any other unconvertible value
+    Zero,
+    /// write a `cleared` field in place of the value, keeping the series' value type intact
+    Null,
+}
+
+/// Per-event settings taken from the command line.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub trim: bool,
+    pub dump_raw: bool,
+    pub series_cache_size: usize,
+    pub empty_value: EmptyValue,
+    pub emit_provenance: bool,
+    pub emit_raw_string: bool,
+    pub enum_string_tag: bool,
+}
+
+/// A property value change as it arrived, before redaction.
+#[derive(Debug, Clone)]
+pub struct PropertyChange {
+    pub device_id: String,
+    pub node_id: String,
+    pub property_id: String,
+    pub value: String,
+    /// seconds, stamped on arrival
+    pub event_time: i64,
+}
+
+/// Turns each property value change into the points written for it: conversion, derived
+/// metrics, sampling, smoothing and field encoding, in that order.
+pub struct Pipeline {
+    pub config: Config,
+    pub converter: Converter,
+    options: Options,
+    control: Arc<Control>,
+    smoother: Smoother,
+    sampler: Sampler,
+    rates: RateTracker,
+    // latest converted value per device/node/property
+    latest: SeriesCache<f64>,
+    // latest raw value per device/node/property, for conditions on modes
+    latest_raw: SeriesCache<String>,
+    /// values no table converts, off unless set up from the command line
+    pub unknown_modes: UnknownModes,
+    // last reading of each device's timestamp source
+    clocks: DeviceClocks,
+}
+
+impl Pipeline {
+    pub fn new(config: Config, options: Options, control: Arc<Control>) -> Pipeline {
+        let pipeline = Pipeline {
+            config,
+            converter: Converter::new(),
+            smoother: Smoother::new(options.series_cache_size),
+            sampler: Sampler::new(options.series_cache_size),
+            rates: RateTracker::new(options.series_cache_size),
+            latest: series_cache(options.series_cache_size),
+            latest_raw: series_cache(options.series_cache_size),
+            unknown_modes: UnknownModes::default(),
+            clocks: DeviceClocks::new(),
+            options,
+            control,
+        };
+        pipeline.check_tables();
+        pipeline
+    }
+
+    /// Warn about mapping tables named in the config that don't exist; a property assigned
+    /// one would have all its non-numeric values fall back to 0.0.
+    fn check_tables(&self) {
+        let tables = self.config.tables.iter().map(|(key, table)| (key.as_str(), table));
+        let ordered = self.config.table_order.iter().map(|table| ("table_order", table));
+        for (key, table) in tables.chain(ordered) {
+            if !self.converter.has_table(table) {
+                warn!("{} is assigned unknown mapping table {}", key, table);
+            }
+        }
+    }
+
+    /// Count how an unconvertible value was handled, unless it was empty, which is counted as
+    /// `empty` alone.
+    fn count_invalid(&self, outcome: &str, raw_value: &str) {
+        if !raw_value.is_empty() {
+            self.control.count_conversion(outcome);
+        }
+    }
+
+    /// Swap in a reloaded config, keeping per-series state.
+    pub fn reload(&mut self, config: Config) {
+        let changed = self.config.changed_sections(&config);
+        if changed.is_empty() {
+            info!("config reloaded, nothing changed");
+        } else {
+            info!("config reloaded, changed: {}", changed.join(", "));
+        }
+        self.config = config;
+        self.check_tables();
+    }
+
+    /// Process a value change of an already-redacted property, stamped with `event_time`.
+    pub fn process(
+        &mut self,
+        device_id: &str,
+        node_id: &str,
+        property_id: &str,
+        value: String,
+        mut event_time: i64
+    ) -> Vec<HomiePoint> {
+        trace!("{}/{}/{} = {} ({})", device_id, node_id, property_id, value, "_");
+
+        if self.options.dump_raw {
+            debug!("{}", raw_dump(device_id, node_id, property_id, &value));
+        }
+
+        // devices often publish a trailing newline or CR, which breaks both
+        // float parsing and the exact-match mode tables
+        let mut raw_value = if self.options.trim {
+            value
+                .trim_matches(|c: char| c.is_whitespace() || c.is_control())
+                .to_string()
+        } else {
+            value
+        };
+
+        if let Some(extract) = config::lookup(&self.config.extract, device_id, node_id, property_id) {
+            match extract.apply(&raw_value) {
+                Some((value, timestamp)) => {
+                    trace!("extracted {} at {:?} from {}", value, timestamp, raw_value);
+                    raw_value = value;
+                    event_time = timestamp.unwrap_or(event_time);
+                }
+                None => {
+                    warn!(
+                        "can't extract a value from {} for {}/{}/{}",
+                        raw_value,
+                        device_id,
+                        node_id,
+                        property_id
+                    );
+                }
+            }
+        }
+
+        if let Some(source) = self.config.timestamp_source.get(device_id) {
+            event_time = self.clocks.timestamp(
+                source,
+                device_id,
+                node_id,
+                property_id,
+                &raw_value,
+                event_time
+            );
+        }
+
+        if raw_value.is_empty() {
+            self.control.count_conversion(convert::EMPTY);
+            match self.options.empty_value {
+                EmptyValue::Skip => {
+                    trace!("skipping empty {}/{}/{}", device_id, node_id, property_id);
+                    return Vec::new();
+                }
+                EmptyValue::Null => {
+                    let mut point = HomiePoint::new(
+                        0.0,
+                        device_id,
+                        node_id,
+                        property_id,
+                        event_time
+                    );
+                    point.fields = vec![("cleared".to_string(), FieldValue::Integer(1))];
+                    return vec![point];
+                }
+                EmptyValue::Zero => {}
+            }
+        }
+
+        // a property named after a table, e.g. target_fan_mode, uses it unless assigned another
+        let table = config::lookup(&self.config.tables, device_id, node_id, property_id)
+            .map(String::as_str)
+            .or_else(|| self.converter.has_table(property_id).then_some(property_id));
+        let mut converted = self.converter.convert(&raw_value, table, &self.config.table_order);
+        if let Some(boolean) = config::lookup(&self.config.boolean, device_id, node_id, property_id) {
+            converted = boolean.apply(converted);
+        }
+        if converted.is_none() && !raw_value.is_empty() {
+            let key = format!("{}/{}/{}", device_id, node_id, property_id);
+            converted = self.unknown_modes
+                .sighting(&key, &raw_value)
+                .map(|index| (index, Outcome::Mapped(convert::UNKNOWN_MODE.to_string())));
+        }
+
+        // without an [invalid] entry NaN and infinity are written as they came
+        let invalid = config::lookup(&self.config.invalid, device_id, node_id, property_id).copied();
+        let mut state = None;
+        let calibration = config::lookup(&self.config.calibration, device_id, node_id, property_id)
+            .copied();
+        let (value, provenance) = match converted {
+            Some((val, outcome)) if val.is_finite() || invalid.is_none() => {
+                self.control.count_conversion(&outcome.label());
+                // only mode names a table knows, so the tag's values stay few
+                if self.options.enum_string_tag && outcome.is_enum() {
+                    state = Some(raw_value.clone());
+                }
+                // fallbacks and sentinels below are written as they are
+                let val = match calibration {
+                    Some(calibration) => {
+                        let calibrated = calibration.apply(val);
+                        trace!("calibrated {} to {}", val, calibrated);
+                        calibrated
+                    }
+                    None => val,
+                };
+                (val, outcome.provenance())
+            }
+            _ =>
+                match invalid.unwrap_or_default() {
+                    Invalid::Zero => {
+                        if raw_value.is_empty() {
+                            // --empty-value zero asked for this, it isn't a failure
+                            trace!("empty {}/{}/{}, setting to 0.0", device_id, node_id, property_id);
+                        } else {
+                            error!(
+                                "can't convert {} to float for {}/{}/{}, setting to 0.0",
+                                raw_value,
+                                device_id,
+                                node_id,
+                                property_id
+                            );
+                        }
+                        self.count_invalid(convert::FALLBACK_ZERO, &raw_value);
+                        (0.0, convert::PROVENANCE_DEFAULT)
+                    }
+                    Invalid::Skip => {
+                        debug!(
+                            "skipping invalid {} for {}/{}/{}",
+                            raw_value,
+                            device_id,
+                            node_id,
+                            property_id
+                        );
+                        self.count_invalid(convert::SKIPPED, &raw_value);
+                        return Vec::new();
+                    }
+                    Invalid::Sentinel { value } => {
+                        debug!(
+                            "writing {} for invalid {} for {}/{}/{}",
+                            value,
+                            raw_value,
+                            device_id,
+                            node_id,
+                            property_id
+                        );
+                        self.count_invalid(convert::SENTINEL, &raw_value);
+                        (value, convert::PROVENANCE_DEFAULT)
+                    }
+                    Invalid::Null => {
+                        self.count_invalid(convert::NULL, &raw_value);
+                        // line protocol needs a field, a point left with none isn't written
+                        let mut point = HomiePoint::new(
+                            0.0,
+                            device_id,
+                            node_id,
+                            property_id,
+                            event_time
+                        );
+                        point.fields.clear();
+                        if self.options.emit_provenance {
+                            point.fields.push(
+                                ("src".to_string(), FieldValue::Integer(convert::PROVENANCE_DEFAULT))
+                            );
+                        }
+                        if self.options.emit_raw_string {
+                            point.fields.push(("raw".to_string(), FieldValue::Text(raw_value)));
+                        }
+                        trace!(
+                            "null for invalid value of {}/{}/{}, {} other field(s)",
+                            device_id,
+                            node_id,
+                            property_id,
+                            point.fields.len()
+                        );
+                        return if point.fields.is_empty() { Vec::new() } else { vec![point] };
+                    }
+                }
+        };
+
+        let key = format!("{}/{}/{}", device_id, node_id, property_id);
+        let mut points = Vec::new();
+
+        let previous = self.latest.put(key.clone(), value);
+        let metric_type = config::lookup(&self.config.metric_types, device_id, node_id, property_id)
+            .copied()
+            .unwrap_or_default();
+        if metric_type == MetricType::Counter && previous.is_some_and(|previous| value < previous) {
+            // written as is, a typed sink's rate() handles the reset
+            info!("counter {} reset from {:?} to {}", key, previous, value);
+            self.control.count_conversion(convert::COUNTER_RESET);
+        }
+        self.latest_raw.put(key.clone(), raw_value.clone());
+
+        // a gated property still updates the latest values above, it may gate others itself
+        if let Some(condition) = config::lookup(&self.config.when, device_id, node_id, property_id) {
+            let gate = format!("{}/{}", device_id, condition.property);
+            let gate_raw = self.latest_raw.get(&gate).map(String::as_str);
+            if !condition.holds(gate_raw, self.latest.get(&gate).copied()) {
+                trace!("{} = {} not forwarded, {} is {:?}", key, value, gate, gate_raw);
+                self.control.count_conversion(convert::SKIPPED);
+                return Vec::new();
+            }
+        }
+        for (pair, delta) in setpoint::deltas(
+            &self.config.target_state,
+            &mut self.latest,
+            device_id,
+            node_id,
+            property_id
+        ) {
+            trace!("{}: {} - {} = {}", device_id, pair.target, pair.state, delta);
+            let state_node = pair.state.split('/').next().unwrap_or_default();
+            points.push(
+                HomiePoint::new(delta, device_id, state_node, "target_vs_state_delta", event_time)
+                    .with_tag("target_tag", &pair.target)
+                    .with_tag("state_tag", &pair.state)
+            );
+        }
+
+        // rates see every value, before sampling thins them out
+        if let Some(rate) = config::lookup(&self.config.rate, device_id, node_id, property_id) {
+            if let Some(per_second) = self.rates.update(&key, rate, value, event_time) {
+                trace!("rate of {} = {}", key, per_second);
+                points.push(
+                    HomiePoint::new(
+                        per_second,
+                        device_id,
+                        node_id,
+                        &format!("{}_rate", property_id),
+                        event_time
+                    )
+                );
+            }
+        }
+
+        // derived points above don't carry provenance, only the property's own value points do
+        let derived = points.len();
+
+        let config = &self.config;
+        if let Some(sampling) = config::lookup(&config.sampling, device_id, node_id, property_id) {
+            if !self.sampler.keep(&key, sampling, event_time) {
+                trace!("sampled out {} = {}", key, value);
+                return points;
+            }
+        }
+
+        match config::lookup(&config.smoothing, device_id, node_id, property_id) {
+            Some(smoothing) => {
+                let smoothed = self.smoother.apply(&key, &smoothing.method, value);
+                trace!("smoothed {} from {} to {}", key, value, smoothed);
+                if smoothing.emit_raw {
+                    points.push(
+                        HomiePoint::new(
+                            value,
+                            device_id,
+                            node_id,
+                            property_id,
+                            event_time
+                        ).with_tag("series", "raw")
+                    );
+                }
+                points.push(
+                    HomiePoint::new(
+                        smoothed,
+                        device_id,
+                        node_id,
+                        property_id,
+                        event_time
+                    ).with_tag("series", "smoothed")
+                );
+            }
+            None => {
+                let mut point = HomiePoint::new(
+                    value,
+                    device_id,
+                    node_id,
+                    property_id,
+                    event_time
+                );
+                // smoothed series stay floats, only plain values are encoded
+                let encoding = config::lookup(
+                    &config.integer_fields,
+                    device_id,
+                    node_id,
+                    property_id
+                );
+                // a calibrated value differs from the raw one, so it is rounded rather than parsed
+                let exact = if calibration.is_some() { "" } else { raw_value.as_str() };
+                match encoding.map(|encoding| encoding.encode(exact, value)) {
+                    None => points.push(point),
+                    Some(Some(field)) => {
+                        point.fields[0].1 = field;
+                        points.push(point);
+                    }
+                    Some(None) => {
+                        self.control.count_conversion(convert::SKIPPED);
+                        warn!(
+                            "dropping negative value {} for unsigned {}/{}/{}",
+                            raw_value,
+                            device_id,
+                            node_id,
+                            property_id
+                        );
+                    }
+                }
+            }
+        }
+
+        if self.options.emit_provenance {
+            for point in &mut points[derived..] {
+                point.fields.push(("src".to_string(), FieldValue::Integer(provenance)));
+            }
+        }
+        if metric_type == MetricType::Counter {
+            for point in &mut points[derived..] {
+                point.tags.push(("metric_type".to_string(), "counter".to_string()));
+            }
+        }
+        if let Some(state) = &state {
+            for point in &mut points[derived..] {
+                point.tags.push(("state".to_string(), state.clone()));
+            }
+        }
+        if self.options.emit_raw_string {
+            for point in &mut points[derived..] {
+                point.fields.push(("raw".to_string(), FieldValue::Text(raw_value.clone())));
+            }
+        }
+
+        points
+    }
+}
+
+/// A span carrying a value's ids as structured fields, for everything logged while converting
+/// it.
+pub fn value_span(device_id: &str, node_id: &str, property_id: &str) -> tracing::Span {
+    tracing::info_span!("value", device = device_id, node = node_id, property = property_id)
+}
+
+/// The `--dump-raw` line for a value, escaped so trailing newlines and other control
+/// characters show up.
+fn raw_dump(device_id: &str, node_id: &str, property_id: &str, value: &str) -> String {
+    format!(
+        "raw {}/{}/{} = \"{}\" ({} bytes)",
+        device_id,
+        node_id,
+        property_id,
+        value.escape_debug(),
+        value.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::VALUE_FIELD;
+
+    fn options() -> Options {
+        Options {
+            trim: true,
+            dump_raw: false,
+            series_cache_size: 16,
+            empty_value: EmptyValue::Zero,
+            emit_provenance: false,
+            emit_raw_string: false,
+            enum_string_tag: false,
+        }
+    }
+
+    fn pipeline_with(config: Config, options: Options) -> (Pipeline, Arc<Control>) {
+        let control = Arc::new(Control::default());
+        (Pipeline::new(config, options, control.clone()), control)
+    }
+
+    fn pipeline(empty_value: EmptyValue) -> (Pipeline, Arc<Control>) {
+        pipeline_with(Config::default(), Options { empty_value, ..options() })
+    }
+
+    #[test]
+    fn empty_value_is_counted_once() {
+        let (mut pipeline, control) = pipeline(EmptyValue::Zero);
+        let points = pipeline.process("thermostat", "zone", "temperature", String::new(), 0);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(0.0));
+        assert_eq!(control.conversion_stats(), "empty=1");
+    }
+
+    #[test]
+    fn float_keeps_its_precision_end_to_end() {
+        let (mut pipeline, _) = pipeline(EmptyValue::Zero);
+        let points = pipeline.process("meter", "energy", "total", "1234567.891234567".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(1_234_567.891_234_567));
+        let line = points[0].to_line_protocol(None);
+        assert!(line.contains("value=1234567.891234567 "), "{}", line);
+    }
+
+    /// Log output captured for a test.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logs_while_processing_carry_the_ids() {
+        use tracing_subscriber::util::SubscriberInitExt;
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let _default = tracing_subscriber
+            ::fmt()
+            .json()
+            .with_current_span(true)
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || writer.clone())
+            .set_default();
+
+        let (mut pipeline, _) = pipeline(EmptyValue::Zero);
+        let span = value_span("thermostat", "zone", "temperature");
+        span.in_scope(|| pipeline.process("thermostat", "zone", "temperature", "n/a".to_string(), 0));
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(!events.is_empty());
+        for event in &events {
+            assert_eq!(event["span"]["device"], "thermostat", "{}", event);
+            assert_eq!(event["span"]["node"], "zone", "{}", event);
+            assert_eq!(event["span"]["property"], "temperature", "{}", event);
+        }
+    }
+
+    #[test]
+    fn raw_dump_escapes_control_characters() {
+        assert_eq!(
+            raw_dump("thermostat", "zone", "temperature", "21.5\r\n"),
+            "raw thermostat/zone/temperature = \"21.5\\r\\n\" (6 bytes)"
+        );
+    }
+
+    #[test]
+    fn values_are_trimmed_before_converting() {
+        let (mut pipeline, control) = pipeline(EmptyValue::Zero);
+        let points = pipeline.process("thermostat", "zone", "temperature", "42.0\n".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(42.0));
+        let points = pipeline.process("thermostat", "zone", "mode", " heating ".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(4.0));
+        assert!(!control.conversion_stats().contains("fallback_zero"), "{}", control.conversion_stats());
+
+        let (mut untrimmed, control) = pipeline_with(Config::default(), Options { trim: false, ..options() });
+        let points = untrimmed.process("thermostat", "zone", "mode", " heating ".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(0.0));
+        assert_eq!(control.conversion_stats(), "fallback_zero=1");
+    }
+
+    #[test]
+    fn provenance_tells_parsed_mapped_and_defaulted_values_apart() {
+        let options = Options { emit_provenance: true, ..options() };
+        let (mut auditing, _) = pipeline_with(Config::default(), options);
+        let src = |points: Vec<HomiePoint>| {
+            points[0].fields.iter().find(|(key, _)| key == "src").map(|(_, value)| value.clone())
+        };
+        let parsed = auditing.process("thermostat", "zone", "temperature", "21.5".to_string(), 0);
+        assert_eq!(src(parsed), Some(FieldValue::Integer(0)));
+        let mapped = auditing.process("thermostat", "zone", "mode", "heating".to_string(), 0);
+        assert_eq!(src(mapped), Some(FieldValue::Integer(1)));
+        let defaulted = auditing.process("thermostat", "zone", "temperature", "n/a".to_string(), 0);
+        assert_eq!(src(defaulted), Some(FieldValue::Integer(convert::PROVENANCE_DEFAULT)));
+
+        let (mut quiet, _) = pipeline(EmptyValue::Zero);
+        assert_eq!(src(quiet.process("thermostat", "zone", "temperature", "21.5".to_string(), 0)), None);
+    }
+
+    #[test]
+    fn mode_string_is_written_with_its_number() {
+        let (mut raw, _) = pipeline_with(Config::default(), Options { emit_raw_string: true, ..options() });
+        let points = raw.process("thermostat", "zone", "mode", "heating".to_string(), 0);
+        assert_eq!(points.len(), 1);
+        assert_eq!(
+            points[0].fields,
+            vec![
+                (VALUE_FIELD.to_string(), FieldValue::Float(4.0)),
+                ("raw".to_string(), FieldValue::Text("heating".to_string()))
+            ]
+        );
+        let line = points[0].to_line_protocol(None);
+        assert!(line.contains(" value=4,raw=\"heating\" "), "{}", line);
+    }
+
+    #[test]
+    fn mode_is_written_as_its_index_with_a_state_tag() {
+        let options = Options { enum_string_tag: true, ..options() };
+        let (mut tagging, _) = pipeline_with(Config::default(), options);
+        let points = tagging.process("thermostat", "zone", "mode", "heating".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(4.0));
+        assert_eq!(points[0].tag("state"), Some("heating"));
+
+        // plain numbers aren't enums, they get no tag
+        let points = tagging.process("thermostat", "zone", "temperature", "21.5".to_string(), 0);
+        assert_eq!(points[0].tag("state"), None);
+        let (mut plain, _) = pipeline(EmptyValue::Zero);
+        let points = plain.process("thermostat", "zone", "mode", "heating".to_string(), 0);
+        assert_eq!(points[0].tag("state"), None);
+    }
+
+    #[test]
+    fn null_policy_omits_the_value_field() {
+        let mut config = Config::default();
+        config.invalid.insert("temperature".to_string(), Invalid::Null);
+        let (mut nulling, control) = pipeline_with(config, options());
+        assert!(nulling.process("thermostat", "zone", "temperature", "n/a".to_string(), 0).is_empty());
+        assert_eq!(control.conversion_stats(), "null=1");
+
+        // with another field the point is written, just without a value
+        let mut config = Config::default();
+        config.invalid.insert("temperature".to_string(), Invalid::Null);
+        let (mut auditing, _) = pipeline_with(config, Options { emit_provenance: true, ..options() });
+        let points = auditing.process("thermostat", "zone", "temperature", "n/a".to_string(), 0);
+        assert_eq!(
+            points[0].fields,
+            vec![("src".to_string(), FieldValue::Integer(convert::PROVENANCE_DEFAULT))]
+        );
+        let line = points[0].to_line_protocol(None);
+        assert!(!line.contains("value="), "{}", line);
+    }
+
+    #[test]
+    fn unconvertible_value_falls_back_to_zero() {
+        let (mut pipeline, control) = pipeline(EmptyValue::Zero);
+        let points = pipeline.process("thermostat", "zone", "temperature", "n/a".to_string(), 0);
+        assert_eq!(points[0].fields[0].1, FieldValue::Float(0.0));
+        assert_eq!(control.conversion_stats(), "fallback_zero=1");
+    }
+
+    #[test]
+    fn empty_value_can_be_skipped_or_cleared() {
+        let (mut skipping, _) = pipeline(EmptyValue::Skip);
+        assert!(skipping.process("thermostat", "zone", "temperature", String::new(), 0).is_empty());
+
+        let (mut clearing, _) = pipeline(EmptyValue::Null);
+        let points = clearing.process("thermostat", "zone", "temperature", String::new(), 0);
+        assert_eq!(points[0].fields, vec![("cleared".to_string(), FieldValue::Integer(1))]);
+    }
+
+    #[test]
+    fn counters_are_tagged_and_gauges_are_not() {
+        let mut config = Config::default();
+        config.metric_types.insert("meter/energy/total".to_string(), MetricType::Counter);
+        let (mut pipeline, _) = pipeline_with(config, options());
+
+        let counter = pipeline.process("meter", "energy", "total", "10".to_string(), 0);
+        assert_eq!(counter[0].tag("metric_type"), Some("counter"));
+        let gauge = pipeline.process("meter", "energy", "power", "10".to_string(), 0);
+        assert_eq!(gauge[0].tag("metric_type"), None);
+    }
+
+    #[test]
+    fn counter_reset_is_written_and_counted() {
+        let mut config = Config::default();
+        config.metric_types.insert("total".to_string(), MetricType::Counter);
+        let (mut pipeline, control) = pipeline_with(config, options());
+
+        pipeline.process("meter", "energy", "total", "10".to_string(), 0);
+        let reset = pipeline.process("meter", "energy", "total", "2".to_string(), 1);
+        assert_eq!(reset[0].fields[0].1, FieldValue::Float(2.0));
+        assert!(control.conversion_stats().contains("counter_reset=1"), "{}", control.conversion_stats());
+    }
+}
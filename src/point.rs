@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use serde::{ Deserialize, Serialize };
+use telegraf::protocol::{ format_attr, Attr, LineProtocol };
+use telegraf::{ IntoFieldData, Point };
+
+use crate::control::PollStats;
+
+/// Measurement name every Homie value is written under.
+pub const MEASUREMENT: &str = "HomieMetric";
+
+/// Measurement for annotation events such as Homie broadcasts.
+pub const ANNOTATION_MEASUREMENT: &str = "HomieAnnotation";
+
+/// Measurement for the bridge's own poll metrics.
+pub const POLL_MEASUREMENT: &str = "HomiePoll";
+
+/// Field a single property value is written to.
+pub const VALUE_FIELD: &str = "value";
+
+// telegraf's line-protocol parser reads timestamps as nanoseconds
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Float(f64),
+    Integer(i64),
+    Unsigned(u64),
+    Boolean(bool),
+    Text(String),
+}
+
+/// How a property configured as an integer field is encoded.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegerEncoding {
+    Signed,
+    Unsigned,
+}
+
+impl IntegerEncoding {
+    /// Encode a value as an integer field, parsing the raw string exactly when possible (a float
+    /// can't hold large counters) and rounding the converted value otherwise. Returns `None`
+    /// for a negative value encoded as unsigned.
+    pub fn encode(&self, raw: &str, value: f64) -> Option<FieldValue> {
+        match self {
+            IntegerEncoding::Signed =>
+                Some(FieldValue::Integer(raw.parse().unwrap_or(value.round() as i64))),
+            IntegerEncoding::Unsigned => {
+                if let Ok(n) = raw.parse() {
+                    Some(FieldValue::Unsigned(n))
+                } else if value >= 0.0 {
+                    Some(FieldValue::Unsigned(value.round() as u64))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Whether a property is a level that goes up and down or a cumulative count, for sinks
+/// that type their metrics.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricType {
+    #[default]
+    Gauge,
+    Counter,
+}
+
+/// Split an array node id such as `zone[2]` into its base `zone` and index `2`.
+pub fn split_array_node(node_id: &str) -> Option<(&str, &str)> {
+    let (base, rest) = node_id.split_once('[')?;
+    let index = rest.strip_suffix(']')?;
+    if base.is_empty() || index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((base, index))
+}
+
+/// A converted Homie property value and the tags it is written with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HomiePoint {
+    pub measurement: String,
+    pub fields: Vec<(String, FieldValue)>,
+    pub tags: Vec<(String, String)>,
+    /// event time in seconds, captured when the value arrived so retried writes are identical
+    pub timestamp: i64,
+}
+
+impl HomiePoint {
+    pub fn new(
+        value: f64,
+        device_id: &str,
+        node_id: &str,
+        property_id: &str,
+        timestamp: i64
+    ) -> HomiePoint {
+        let mut tags = vec![
+            ("device_id_tag".to_string(), device_id.to_string()),
+            ("node_id_tag".to_string(), node_id.to_string()),
+            ("property_id_tag".to_string(), property_id.to_string())
+        ];
+        if let Some((base, index)) = split_array_node(node_id) {
+            tags.push(("node_base_tag".to_string(), base.to_string()));
+            tags.push(("index_tag".to_string(), index.to_string()));
+        }
+
+        HomiePoint {
+            measurement: MEASUREMENT.to_string(),
+            fields: vec![(VALUE_FIELD.to_string(), FieldValue::Float(value))],
+            timestamp,
+            tags,
+        }
+    }
+
+    /// A metric about a device as a whole, written with just its device id and the metric's
+    /// `name` as property id.
+    pub fn device_metric(name: &str, value: f64, device_id: &str, timestamp: i64) -> HomiePoint {
+        HomiePoint {
+            measurement: MEASUREMENT.to_string(),
+            fields: vec![(VALUE_FIELD.to_string(), FieldValue::Float(value))],
+            timestamp,
+            tags: vec![
+                ("device_id_tag".to_string(), device_id.to_string()),
+                ("property_id_tag".to_string(), name.to_string())
+            ],
+        }
+    }
+
+    /// An annotation of the given `kind` carrying `text`, kept apart from device values.
+    pub fn annotation(kind: &str, text: &str, timestamp: i64) -> HomiePoint {
+        HomiePoint {
+            measurement: ANNOTATION_MEASUREMENT.to_string(),
+            fields: vec![("text".to_string(), FieldValue::Text(text.to_string()))],
+            timestamp,
+            tags: vec![("kind".to_string(), kind.to_string())],
+        }
+    }
+
+    /// The poll metrics of a status interval, `poll_duration_ms` and `poll_batch_size` being
+    /// means over its polls, with the running count of each kind of poll error.
+    pub fn poll_metrics(
+        stats: &PollStats,
+        errors: &BTreeMap<String, u64>,
+        timestamp: i64
+    ) -> HomiePoint {
+        let errors = errors
+            .iter()
+            .map(|(kind, count)| (format!("poll_error_{}", kind), FieldValue::Unsigned(*count)));
+        HomiePoint {
+            measurement: POLL_MEASUREMENT.to_string(),
+            fields: vec![
+                ("polls".to_string(), FieldValue::Unsigned(stats.polls)),
+                ("poll_duration_ms".to_string(), FieldValue::Float(stats.mean_duration_ms())),
+                (
+                    "poll_duration_max_ms".to_string(),
+                    FieldValue::Float(stats.max_duration.as_secs_f64() * 1000.0),
+                ),
+                ("poll_batch_size".to_string(), FieldValue::Float(stats.mean_events())),
+                ("poll_batch_max".to_string(), FieldValue::Unsigned(stats.max_events))
+            ]
+                .into_iter()
+                .chain(errors)
+                .collect(),
+            timestamp,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_tag(mut self, key: &str, value: &str) -> HomiePoint {
+        self.tags.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Build the telegraf point, rounding floats to `precision` decimal places if given. The
+    /// point carries its own timestamp in nanoseconds, so telegraf doesn't stamp it on arrival.
+    pub fn to_telegraf(&self, precision: Option<usize>) -> Point {
+        let fields: Vec<(String, Box<dyn IntoFieldData>)> = self.fields
+            .iter()
+            .map(|(key, value)| {
+                let value: Box<dyn IntoFieldData> = match value {
+                    FieldValue::Float(f) => Box::new(round_float(*f, precision)),
+                    FieldValue::Integer(i) => Box::new(*i),
+                    FieldValue::Unsigned(u) => Box::new(*u),
+                    FieldValue::Boolean(b) => Box::new(*b),
+                    FieldValue::Text(s) => Box::new(s.clone()),
+                };
+                (key.clone(), value)
+            })
+            .collect();
+        // a time before the epoch can't be written, telegraf stamps those instead
+        let timestamp = u64::try_from(self.timestamp).ok().map(|secs| secs * NANOS_PER_SEC);
+        Point::new(self.measurement.clone(), self.tags.clone(), fields, timestamp)
+    }
+
+    /// The line the telegraf client sends for this point, byte for byte. Its serializer sorts
+    /// tags and fields and only escapes spaces, so this isn't `to_line_protocol`'s line.
+    pub fn to_telegraf_line(&self, precision: Option<usize>) -> String {
+        let point = self.to_telegraf(precision);
+        let tags: Vec<Attr> = point.tags.into_iter().map(Attr::Tag).collect();
+        let tags = (!tags.is_empty()).then(|| format_attr(tags));
+        let fields = format_attr(point.fields.into_iter().map(Attr::Field).collect());
+        let timestamp = point.timestamp.map(|timestamp| format_attr(vec![Attr::Timestamp(timestamp)]));
+        LineProtocol::new(point.measurement, tags, fields, timestamp).to_str().to_string()
+    }
+
+    /// The point in telegraf's JSON serializer shape, `{"name", "tags", "fields", "timestamp"}`,
+    /// rounding floats to `precision` decimal places if given.
+    pub fn to_telegraf_json(&self, precision: Option<usize>) -> serde_json::Value {
+        let tags: serde_json::Map<String, serde_json::Value> = self.tags
+            .iter()
+            .map(|(key, value)| (key.clone(), serde_json::Value::from(value.as_str())))
+            .collect();
+        let fields: serde_json::Map<String, serde_json::Value> = self.fields
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    FieldValue::Float(f) => serde_json::Value::from(round_float(*f, precision)),
+                    FieldValue::Integer(i) => serde_json::Value::from(*i),
+                    FieldValue::Unsigned(u) => serde_json::Value::from(*u),
+                    FieldValue::Boolean(b) => serde_json::Value::from(*b),
+                    FieldValue::Text(s) => serde_json::Value::from(s.as_str()),
+                };
+                (key.clone(), value)
+            })
+            .collect();
+        serde_json::json!({
+            "name": self.measurement,
+            "tags": tags,
+            "fields": fields,
+            "timestamp": self.timestamp,
+        })
+    }
+
+    /// Build the influx point under a measurement other than the point's own.
+    pub fn to_influx_as(&self, measurement: &str) -> influxdb_rs::Point<'_> {
+        let mut point = influxdb_rs::Point::new(measurement);
+        for (key, value) in &self.tags {
+            point = point.add_tag(key.clone(), value.clone());
+        }
+        for (key, value) in &self.fields {
+            point = match value {
+                FieldValue::Float(f) => point.add_field(key.clone(), *f),
+                FieldValue::Integer(i) => point.add_field(key.clone(), *i),
+                // influxdb_rs has no unsigned type. Unsigned properties are refused for this
+                // sink at startup, so only the bridge's own counters get here, and saturate
+                FieldValue::Unsigned(u) =>
+                    point.add_field(key.clone(), i64::try_from(*u).unwrap_or(i64::MAX)),
+                FieldValue::Boolean(b) => point.add_field(key.clone(), *b),
+                FieldValue::Text(s) => point.add_field(key.clone(), s.clone()),
+            };
+        }
+        point.add_timestamp(self.timestamp)
+    }
+
+    /// Serialize as a line-protocol line with a timestamp in seconds, formatting floats with
+    /// `precision` decimal places if given.
+    pub fn to_line_protocol(&self, precision: Option<usize>) -> String {
+        let mut line = escape_measurement(&self.measurement);
+        for (key, value) in &self.tags {
+            line.push_str(&format!(",{}={}", escape_tag(key), escape_tag(value)));
+        }
+        let fields = self.fields
+            .iter()
+            .map(|(key, value)| {
+                match value {
+                    FieldValue::Float(f) =>
+                        format!("{}={}", escape_tag(key), format_float(*f, precision)),
+                    FieldValue::Integer(i) => format!("{}={}i", escape_tag(key), i),
+                    FieldValue::Unsigned(u) => format!("{}={}u", escape_tag(key), u),
+                    FieldValue::Boolean(b) => format!("{}={}", escape_tag(key), b),
+                    FieldValue::Text(s) => format!("{}=\"{}\"", escape_tag(key), escape_string(s)),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        line.push_str(&format!(" {} {}", fields, self.timestamp));
+        line
+    }
+}
+
+/// Format a float for a textual sink, e.g. `21.3` rather than `21.299999` at precision 1.
+pub fn format_float(value: f64, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision, value),
+        None => value.to_string(),
+    }
+}
+
+fn round_float(value: f64, precision: Option<usize>) -> f64 {
+    match precision {
+        Some(precision) => {
+            let scale = (10f64).powi(precision as i32);
+            (value * scale).round() / scale
+        }
+        None => value,
+    }
+}
+
+/// Escape a measurement name for line protocol.
+fn escape_measurement(s: &str) -> String {
+    s.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escape a tag key or value for line protocol.
+fn escape_tag(s: &str) -> String {
+    s.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Escape a string field value for line protocol.
+fn escape_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_node_is_split_into_base_and_index_tags() {
+        let point = HomiePoint::new(21.5, "thermostat", "zone[2]", "temperature", 1_700_000_000);
+        assert_eq!(point.tag("node_id_tag"), Some("zone[2]"));
+        assert_eq!(point.tag("node_base_tag"), Some("zone"));
+        assert_eq!(point.tag("index_tag"), Some("2"));
+
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        assert_eq!(point.tag("node_base_tag"), None);
+        assert_eq!(split_array_node("zone[]"), None);
+        assert_eq!(split_array_node("zone[a]"), None);
+        assert_eq!(split_array_node("[2]"), None);
+    }
+
+    #[test]
+    fn telegraf_point_keeps_its_timestamp() {
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        let telegraf = point.to_telegraf(None);
+        assert_eq!(telegraf.timestamp.map(|t| t.value), Some(1_700_000_000_000_000_000));
+    }
+
+    #[test]
+    fn telegraf_point_before_the_epoch_is_left_unstamped() {
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", -1);
+        assert_eq!(point.to_telegraf(None).timestamp, None);
+    }
+
+    #[test]
+    fn unsigned_property_is_a_u_suffixed_integer() {
+        let value = IntegerEncoding::Unsigned.encode("18446744073709551615", 0.0);
+        assert_eq!(value, Some(FieldValue::Unsigned(u64::MAX)));
+        let mut point = HomiePoint::new(0.0, "meter", "energy", "pulses", 1_700_000_000);
+        point.fields = vec![("value".to_string(), value.unwrap())];
+        let line = point.to_line_protocol(None);
+        assert!(line.contains("value=18446744073709551615u"), "{}", line);
+    }
+
+    #[test]
+    fn negative_unsigned_value_is_dropped() {
+        assert_eq!(IntegerEncoding::Unsigned.encode("-3", -3.0), None);
+        assert_eq!(IntegerEncoding::Signed.encode("-3", -3.0), Some(FieldValue::Integer(-3)));
+    }
+
+    #[test]
+    fn floats_render_with_the_configured_precision() {
+        assert_eq!(format_float(21.299999, Some(1)), "21.3");
+        assert_eq!(format_float(21.0, Some(2)), "21.00");
+        assert_eq!(format_float(21.299999, None), "21.299999");
+
+        let point = HomiePoint::new(21.299999, "thermostat", "zone", "temperature", 1_700_000_000);
+        let line = point.to_line_protocol(Some(1));
+        assert!(line.contains("value=21.3 "), "{}", line);
+        let line = point.to_telegraf_line(Some(1));
+        assert!(line.contains("value=21.3 "), "{}", line);
+        assert_eq!(point.to_telegraf_json(Some(1))["fields"]["value"], 21.3);
+    }
+}
@@ -0,0 +1,201 @@
+#[cfg(feature = "alloc-profile")]
+use std::alloc::{ GlobalAlloc, Layout, System };
+#[cfg(feature = "alloc-profile")]
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+#[cfg(feature = "alloc-profile")]
+static COUNT_ALLOCS: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "alloc-profile")]
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting allocations once `--profile-allocs` turns it on. Only
+/// built with the `alloc-profile` feature, so normal builds keep the plain allocator.
+#[cfg(feature = "alloc-profile")]
+pub struct CountingAlloc;
+
+#[cfg(feature = "alloc-profile")]
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNT_ALLOCS.load(Ordering::Relaxed) {
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Phases of the main loop timed with `--profile`.
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    Poll,
+    Convert,
+    Write,
+}
+
+// upper bounds in microseconds of the per-value conversion latency buckets
+const LATENCY_BOUNDS_US: &[u64] = &[1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000];
+
+/// Distribution of the time taken to convert single values.
+#[derive(Debug, Default)]
+pub struct Latencies {
+    // one more bucket than bounds, for anything slower
+    buckets: [u64; LATENCY_BOUNDS_US.len() + 1],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Latencies {
+    pub fn add(&mut self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        let bucket = LATENCY_BOUNDS_US.iter()
+            .position(|bound| us <= *bound)
+            .unwrap_or(LATENCY_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, e.g. `<=20us`.
+    fn quantile(&self, q: f64) -> String {
+        let rank = ((self.count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return match LATENCY_BOUNDS_US.get(bucket) {
+                    Some(bound) => format!("<={}us", bound),
+                    None => format!(">{}us", LATENCY_BOUNDS_US[LATENCY_BOUNDS_US.len() - 1]),
+                };
+            }
+        }
+        "-".to_string()
+    }
+
+    pub fn report(&self) -> String {
+        if self.count == 0 {
+            return "convert_latency n=0".to_string();
+        }
+        format!(
+            "convert_latency n={} mean={:.1}us p50{} p99{} max={}us",
+            self.count,
+            (self.total.as_secs_f64() * 1e6) / (self.count as f64),
+            self.quantile(0.5),
+            self.quantile(0.99),
+            self.max.as_micros()
+        )
+    }
+}
+
+/// Time spent per phase since the last report.
+#[derive(Debug)]
+pub struct Profiler {
+    since: Instant,
+    poll: Duration,
+    convert: Duration,
+    write: Duration,
+    latencies: Latencies,
+}
+
+impl Profiler {
+    pub fn new(count_allocs: bool, now: Instant) -> Profiler {
+        #[cfg(feature = "alloc-profile")]
+        COUNT_ALLOCS.store(count_allocs, Ordering::Relaxed);
+        #[cfg(not(feature = "alloc-profile"))]
+        if count_allocs {
+            warn!("--profile-allocs needs a build with the alloc-profile feature, not counting");
+        }
+        Profiler {
+            since: now,
+            poll: Duration::ZERO,
+            convert: Duration::ZERO,
+            write: Duration::ZERO,
+            latencies: Latencies::default(),
+        }
+    }
+
+    /// Record how long converting a single value took.
+    pub fn add_conversion(&mut self, elapsed: Duration) {
+        self.latencies.add(elapsed);
+    }
+
+    pub fn add(&mut self, phase: Phase, elapsed: Duration) {
+        match phase {
+            Phase::Poll => self.poll += elapsed,
+            Phase::Convert => self.convert += elapsed,
+            Phase::Write => self.write += elapsed,
+        }
+    }
+
+    /// Each phase as a share of the time since the last report, up to `now`, then start over.
+    pub fn report(&mut self, now: Instant) -> String {
+        let elapsed = now.duration_since(self.since);
+        let total = elapsed.as_secs_f64().max(f64::EPSILON);
+        let share = |phase: Duration| (100.0 * phase.as_secs_f64()) / total;
+        let other = elapsed.saturating_sub(self.poll + self.convert + self.write);
+        let mut report = format!(
+            "poll={:.1}% convert={:.1}% write={:.1}% other={:.1}% over {:.0}s",
+            share(self.poll),
+            share(self.convert),
+            share(self.write),
+            share(other),
+            total
+        );
+        #[cfg(feature = "alloc-profile")]
+        if COUNT_ALLOCS.load(Ordering::Relaxed) {
+            report.push_str(&format!(" allocs={}", ALLOCS.swap(0, Ordering::Relaxed)));
+        }
+        report.push_str(&format!(" {}", self.latencies.report()));
+
+        *self = Profiler {
+            since: now,
+            poll: Duration::ZERO,
+            convert: Duration::ZERO,
+            write: Duration::ZERO,
+            latencies: Latencies::default(),
+        };
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_phase_shares_and_starts_over() {
+        let start = Instant::now();
+        let mut profiler = Profiler::new(false, start);
+        profiler.add(Phase::Poll, Duration::from_secs(5));
+        profiler.add(Phase::Convert, Duration::from_secs(2));
+        profiler.add(Phase::Write, Duration::from_secs(1));
+        let report = profiler.report(start + Duration::from_secs(10));
+        assert!(
+            report.starts_with("poll=50.0% convert=20.0% write=10.0% other=20.0% over 10s"),
+            "{}",
+            report
+        );
+
+        let report = profiler.report(start + Duration::from_secs(20));
+        assert!(report.starts_with("poll=0.0% convert=0.0% write=0.0% other=100.0%"), "{}", report);
+    }
+
+    #[test]
+    fn latency_quantiles_are_bucket_bounds() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.report(), "convert_latency n=0");
+        for us in [1, 3, 3, 15, 40_000] {
+            latencies.add(Duration::from_micros(us));
+        }
+        assert_eq!(latencies.quantile(0.5), "<=5us");
+        assert_eq!(latencies.quantile(0.99), ">10000us");
+        assert!(latencies.report().ends_with("max=40000us"));
+    }
+}
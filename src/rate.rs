@@ -0,0 +1,77 @@
+use serde::{ Deserialize, Serialize };
+
+use crate::cache::{ series_cache, SeriesCache };
+
+/// Derive a `<property>_rate` metric from a cumulative counter, e.g. kWh into watts.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    /// multiplier applied to delta/seconds, e.g. 3600000 for kWh to W (1)
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Last value and time per counter series.
+#[derive(Debug)]
+pub struct RateTracker {
+    series: SeriesCache<(f64, i64)>,
+}
+
+impl RateTracker {
+    pub fn new(capacity: usize) -> RateTracker {
+        RateTracker { series: series_cache(capacity) }
+    }
+
+    /// The scaled per-second rate since the last value of the series at `key`. None for the
+    /// first value, when no time has passed, and when the counter went down (a reset).
+    pub fn update(&mut self, key: &str, rate: &Rate, value: f64, timestamp: i64) -> Option<f64> {
+        let (last_value, last_time) = self.series.put(key.to_string(), (value, timestamp))?;
+        let seconds = timestamp - last_time;
+        if seconds <= 0 {
+            // keep the earlier sample so the next rate spans a real interval
+            self.series.put(key.to_string(), (last_value, last_time));
+            return None;
+        }
+        if value < last_value {
+            trace!("counter reset on {}: {} -> {}", key, last_value, value);
+            return None;
+        }
+        Some(((value - last_value) / (seconds as f64)) * rate.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KWH_TO_W: Rate = Rate { scale: 3_600_000.0 };
+
+    #[test]
+    fn rate_is_scaled_delta_per_second() {
+        let mut tracker = RateTracker::new(10);
+        assert_eq!(tracker.update("meter/energy", &KWH_TO_W, 10.0, 1_000), None);
+        let watts = tracker.update("meter/energy", &KWH_TO_W, 10.001, 1_010).unwrap();
+        assert!((watts - 360.0).abs() < 1e-6, "{}", watts);
+    }
+
+    #[test]
+    fn counter_reset_gives_no_rate() {
+        let mut tracker = RateTracker::new(10);
+        tracker.update("meter/energy", &KWH_TO_W, 10.0, 1_000);
+        assert_eq!(tracker.update("meter/energy", &KWH_TO_W, 0.5, 1_010), None);
+        // the rate picks up again from the reset value
+        assert_eq!(tracker.update("meter/energy", &Rate { scale: 1.0 }, 1.5, 1_020), Some(0.1));
+    }
+
+    #[test]
+    fn zero_interval_keeps_the_earlier_sample() {
+        let mut tracker = RateTracker::new(10);
+        let rate = Rate { scale: 1.0 };
+        tracker.update("meter/energy", &rate, 10.0, 1_000);
+        assert_eq!(tracker.update("meter/energy", &rate, 12.0, 1_000), None);
+        assert_eq!(tracker.update("meter/energy", &rate, 14.0, 1_002), Some(2.0));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{ BTreeSet, HashMap };
+
+use homie_controller::Device;
+
+use crate::pipeline::PropertyChange;
+
+/// Holds the value changes of devices that haven't published their required attributes yet,
+/// releasing them in arrival order once the device is fully described.
+#[derive(Debug)]
+pub struct ReadyGate {
+    pending: HashMap<String, Vec<PropertyChange>>,
+    // most changes held per device, oldest are dropped
+    limit: usize,
+}
+
+impl ReadyGate {
+    pub fn new(limit: usize) -> ReadyGate {
+        ReadyGate { pending: HashMap::new(), limit }
+    }
+
+    /// The changes ready to process: held changes of devices that became ready, then the
+    /// new `changes` of ready devices. The rest are held.
+    pub fn pass(
+        &mut self,
+        changes: Vec<PropertyChange>,
+        devices: &HashMap<String, Device>
+    ) -> Vec<PropertyChange> {
+        let is_ready = |device_id: &str| {
+            devices.get(device_id).is_some_and(|device| device.has_required_attributes())
+        };
+
+        let mut ready = Vec::new();
+        let now_ready: Vec<String> = self.pending
+            .keys()
+            .filter(|device_id| is_ready(device_id))
+            .cloned()
+            .collect();
+        for device_id in now_ready {
+            if let Some(held) = self.pending.remove(&device_id) {
+                info!("{} is ready, releasing {} held value(s)", device_id, held.len());
+                ready.extend(held);
+            }
+        }
+
+        for change in changes {
+            if is_ready(&change.device_id) {
+                ready.push(change);
+                continue;
+            }
+            let held = self.pending.entry(change.device_id.clone()).or_default();
+            if held.len() >= self.limit {
+                warn!("{} not ready, dropping its oldest held value", change.device_id);
+                held.remove(0);
+            }
+            trace!("{} not ready, holding {}/{}", change.device_id, change.node_id, change.property_id);
+            held.push(change);
+        }
+        ready
+    }
+}
+
+/// The devices last seen ready, so readiness is logged as one summary line when they change
+/// instead of a line per device on every update.
+#[derive(Debug, Default)]
+pub struct ReadySet {
+    ready: BTreeSet<String>,
+}
+
+impl ReadySet {
+    /// The summary to log if the devices ready among `devices` changed since the last update.
+    pub fn update(&mut self, devices: &HashMap<String, Device>) -> Option<String> {
+        let ready: BTreeSet<String> = devices
+            .values()
+            .filter(|device| device.has_required_attributes())
+            .map(|device| device.id.clone())
+            .collect();
+        if ready == self.ready {
+            return None;
+        }
+        self.ready = ready;
+        Some(format!("{} of {} device(s) ready", self.ready.len(), devices.len()))
+    }
+}
+
+/// A line per device saying whether it is ready, for `--log-readiness`, with each id passed
+/// through `redact`.
+pub fn readiness_lines(devices: &HashMap<String, Device>, redact: impl Fn(&str) -> String) -> Vec<String> {
+    devices
+        .values()
+        .map(|device| {
+            if device.has_required_attributes() {
+                format!(" * {}", redact(&device.id))
+            } else {
+                format!(" * {} not ready.", redact(&device.id))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use homie_controller::State;
+
+    use super::*;
+
+    fn device(id: &str, name: Option<&str>) -> Device {
+        Device {
+            id: id.to_string(),
+            homie_version: "4.0".to_string(),
+            name: name.map(str::to_string),
+            state: State::Ready,
+            implementation: None,
+            nodes: HashMap::new(),
+            extensions: Vec::new(),
+            local_ip: None,
+            mac: None,
+            firmware_name: None,
+            firmware_version: None,
+            stats_interval: None,
+            stats_uptime: None,
+            stats_signal: None,
+            stats_cputemp: None,
+            stats_cpuload: None,
+            stats_battery: None,
+            stats_freeheap: None,
+            stats_supply: None,
+        }
+    }
+
+    fn change(device_id: &str, value: &str) -> PropertyChange {
+        PropertyChange {
+            device_id: device_id.to_string(),
+            node_id: "zone".to_string(),
+            property_id: "temperature".to_string(),
+            value: value.to_string(),
+            event_time: 0,
+        }
+    }
+
+    fn values(changes: &[PropertyChange]) -> Vec<&str> {
+        changes.iter().map(|change| change.value.as_str()).collect()
+    }
+
+    #[test]
+    fn changes_are_held_until_the_device_is_ready() {
+        let mut gate = ReadyGate::new(10);
+        let mut devices = HashMap::from([("thermostat".to_string(), device("thermostat", None))]);
+
+        assert!(gate.pass(vec![change("thermostat", "1"), change("thermostat", "2")], &devices).is_empty());
+
+        devices.insert("thermostat".to_string(), device("thermostat", Some("Thermostat")));
+        let ready = gate.pass(vec![change("thermostat", "3")], &devices);
+        assert_eq!(values(&ready), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn oldest_held_changes_are_dropped_past_the_limit() {
+        let mut gate = ReadyGate::new(2);
+        let mut devices = HashMap::new();
+        let changes = vec![change("thermostat", "1"), change("thermostat", "2"), change("thermostat", "3")];
+        assert!(gate.pass(changes, &devices).is_empty());
+
+        devices.insert("thermostat".to_string(), device("thermostat", Some("Thermostat")));
+        assert_eq!(values(&gate.pass(Vec::new(), &devices)), ["2", "3"]);
+    }
+
+    #[test]
+    fn summary_only_when_the_ready_devices_change() {
+        let mut ready = ReadySet::default();
+        let mut devices = HashMap::from([("thermostat".to_string(), device("thermostat", None))]);
+        assert_eq!(ready.update(&devices), None);
+
+        devices.insert("thermostat".to_string(), device("thermostat", Some("Thermostat")));
+        devices.insert("heater".to_string(), device("heater", None));
+        assert_eq!(ready.update(&devices).as_deref(), Some("1 of 2 device(s) ready"));
+        assert_eq!(ready.update(&devices), None);
+    }
+
+    #[test]
+    fn readiness_lines_name_each_device() {
+        let devices = HashMap::from([("heater".to_string(), device("heater", None))]);
+        let lines = readiness_lines(&devices, |id| id.to_uppercase());
+        assert_eq!(lines, [" * HEATER not ready."]);
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::config::RedactRule;
+
+/// Rewrites device/node/property ids before they are logged or used as tags, so a
+/// topology can be anonymized while still producing consistent series.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(rules: &[RedactRule]) -> Result<Redactor, regex::Error> {
+        let rules = rules
+            .iter()
+            .map(|rule| Ok((Regex::new(&rule.pattern)?, rule.replacement.clone())))
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+        Ok(Redactor { rules })
+    }
+
+    /// Apply every rule in config order.
+    pub fn apply<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        let mut id = Cow::Borrowed(id);
+        for (pattern, replacement) in &self.rules {
+            let redacted = match pattern.replace_all(&id, replacement.as_str()) {
+                Cow::Owned(redacted) => Some(redacted),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(redacted) = redacted {
+                id = Cow::Owned(redacted);
+            }
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, replacement: &str) -> RedactRule {
+        RedactRule { pattern: pattern.to_string(), replacement: replacement.to_string() }
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let redactor = Redactor::new(&[
+            rule("^smith-house-", "home-"),
+            rule("[0-9a-f]{12}", "<mac>"),
+            rule("home-<mac>", "home-device")
+        ]).unwrap();
+        assert_eq!(redactor.apply("smith-house-a4cf12b3c4d5"), "home-device");
+        assert_eq!(redactor.apply("office-a4cf12b3c4d5"), "office-<mac>");
+    }
+
+    #[test]
+    fn leaves_unmatched_ids_borrowed() {
+        let redactor = Redactor::new(&[rule("secret", "x")]).unwrap();
+        assert!(matches!(redactor.apply("thermostat"), Cow::Borrowed("thermostat")));
+        assert_eq!(Redactor::default().apply("secret"), "secret");
+    }
+
+    #[test]
+    fn rejects_an_invalid_pattern() {
+        assert!(Redactor::new(&[rule("(unclosed", "x")]).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::{ Path, PathBuf };
+
+use tokio::signal::unix::{ signal, SignalKind };
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::Config;
+use crate::device_tags::DeviceTagger;
+use crate::redact::Redactor;
+
+/// A config that loaded and validated, ready to replace the running one.
+pub struct Reloaded {
+    pub config: Config,
+    pub redactor: Redactor,
+    pub device_tagger: DeviceTagger,
+}
+
+/// Load and validate the config at `source`; on error the running config must be kept.
+pub async fn load_validated(
+    source: &str,
+    cache: &Path,
+    strict: bool
+) -> Result<Reloaded, String> {
+    let config = Config::load(source, cache, strict).await.map_err(|e| e.to_string())?;
+    let redactor = Redactor::new(&config.redact).map_err(|e|
+        format!("invalid redact pattern: {}", e)
+    )?;
+    let device_tagger = DeviceTagger::new(&config.device_tags).map_err(|e|
+        format!("invalid device tag pattern: {}", e)
+    )?;
+    Ok(Reloaded { config, redactor, device_tagger })
+}
+
+/// Reloads the config file on request, handing each valid config to the main loop. Only the
+/// config file is reloaded; connection settings come from the command line and stay put.
+#[derive(Debug, Clone)]
+pub struct Reloader {
+    pub source: String,
+    pub cache: PathBuf,
+    pub strict: bool,
+    pub reloads: UnboundedSender<Reloaded>,
+}
+
+impl Reloader {
+    /// Load and validate the config, queueing it to be applied before the next poll. On error
+    /// the running config is kept.
+    pub async fn reload(&self) -> Result<(), String> {
+        let reloaded = load_validated(&self.source, &self.cache, self.strict).await?;
+        self.reloads.send(reloaded).map_err(|_| "the main loop has stopped".to_string())
+    }
+}
+
+/// Reload the config on every SIGHUP.
+pub fn watch_sighup(reloader: Reloader) -> std::io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP: reloading config from {}", reloader.source);
+            if let Err(e) = reloader.reload().await {
+                error!("config reload failed, keeping the current config: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("homie-reload-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn reloader(source: &Path, reloads: UnboundedSender<Reloaded>) -> Reloader {
+        Reloader {
+            source: source.display().to_string(),
+            cache: std::env::temp_dir().join("homie-reload-unused-cache.toml"),
+            strict: true,
+            reloads,
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_config_is_queued() {
+        let path = config_file("valid", "[[redact]]\npattern = \"^secret-(.*)$\"\nreplacement = \"$1\"\n");
+        let (sender, mut receiver) = unbounded_channel();
+        reloader(&path, sender).reload().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let reloaded = receiver.try_recv().unwrap();
+        assert_eq!(reloaded.config.redact.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_config_is_not_queued() {
+        let path = config_file("invalid", "[[redact]]\npattern = \"(\"\nreplacement = \"\"\n");
+        let (sender, mut receiver) = unbounded_channel();
+        let err = reloader(&path, sender).reload().await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(err.starts_with("invalid redact pattern"), "{}", err);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn reload_fails_once_the_main_loop_has_stopped() {
+        let path = config_file("stopped", "");
+        let (sender, receiver) = unbounded_channel();
+        drop(receiver);
+        let err = reloader(&path, sender).reload().await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(err, "the main loop has stopped");
+    }
+}
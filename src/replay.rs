@@ -0,0 +1,419 @@
+use std::fs::{ self, File };
+use std::io::{ self, BufRead, BufReader };
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use chrono::Utc;
+use flate2::read::MultiGzDecoder;
+use tokio::time::Instant;
+
+use crate::deadletter::Deadletter;
+use crate::point::{ FieldValue, HomiePoint };
+use crate::sink::Sink;
+
+// points handed to the sink at a time, so batching sinks send few requests
+const REPLAY_CHUNK: usize = 1_000;
+
+// leading bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// What a replay did.
+#[derive(Debug, Default)]
+pub struct ReplayStats {
+    pub written: u64,
+    pub failed: u64,
+    pub malformed: u64,
+    /// older than --max-point-age
+    pub stale: u64,
+}
+
+/// Write every point in the line-protocol file at `path` through `sink`, keeping its
+/// timestamp. Deadletter files can be replayed as they are, the line is their last column.
+/// A directory of rotated files is replayed segment by segment, oldest first; gzipped files
+/// are decompressed. Files are streamed, not read whole. With a `speed` above 0 points are
+/// written with the gaps between their timestamps divided by it, so 60 replays an hour in a
+/// minute; at 0 they are written as fast as possible. Points older than `max_point_age`
+/// seconds are dropped, as they would be live.
+pub async fn replay(
+    path: &Path,
+    sink: &mut Sink,
+    deadletter: &mut Deadletter,
+    speed: f64,
+    max_point_age: Option<u64>
+) -> io::Result<ReplayStats> {
+    let segments = if path.is_dir() {
+        let segments = segments_in(path)?;
+        info!("replaying {} segment(s) from {}", segments.len(), path.display());
+        segments
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut replayer = Replayer {
+        sink,
+        deadletter,
+        speed,
+        max_point_age,
+        started: Instant::now(),
+        first: None,
+        pending: Vec::new(),
+        stats: ReplayStats::default(),
+    };
+    for segment in &segments {
+        debug!("replaying {}", segment.display());
+        for (number, line) in open_lines(segment)?.lines().enumerate() {
+            let line = line?;
+            let Some(line) = entry(&line) else {
+                continue;
+            };
+            match parse_line_protocol(line) {
+                Some(point) => replayer.push(point).await,
+                None => {
+                    warn!("skipping malformed line {} of {}: {}", number + 1, segment.display(), line);
+                    replayer.stats.malformed += 1;
+                }
+            }
+        }
+    }
+    replayer.flush().await;
+    Ok(replayer.stats)
+}
+
+/// Writes replayed points as they are read, in chunks, or paced by their timestamps.
+struct Replayer<'a> {
+    sink: &'a mut Sink,
+    deadletter: &'a mut Deadletter,
+    speed: f64,
+    max_point_age: Option<u64>,
+    started: Instant,
+    // the timestamp the pacing counts from
+    first: Option<i64>,
+    pending: Vec<HomiePoint>,
+    stats: ReplayStats,
+}
+
+impl Replayer<'_> {
+    async fn push(&mut self, point: HomiePoint) {
+        if is_stale(&point, self.max_point_age, Utc::now().timestamp()) {
+            self.stats.stale += 1;
+            return;
+        }
+        // paced, points sharing a timestamp go together
+        let due = if self.speed > 0.0 {
+            self.pending.first().is_some_and(|pending| pending.timestamp != point.timestamp)
+        } else {
+            self.pending.len() >= REPLAY_CHUNK
+        };
+        if due {
+            self.flush().await;
+        }
+        self.pending.push(point);
+    }
+
+    /// Write the pending points, once it is their time if paced; a step back in time is
+    /// written right away.
+    async fn flush(&mut self) {
+        let Some(timestamp) = self.pending.first().map(|point| point.timestamp) else {
+            return;
+        };
+        if self.speed > 0.0 {
+            let first = *self.first.get_or_insert(timestamp);
+            let offset = ((timestamp - first).max(0) as f64) / self.speed;
+            tokio::time::sleep_until(self.started + Duration::from_secs_f64(offset)).await;
+        }
+        let points = std::mem::take(&mut self.pending);
+        write_chunks(points, self.sink, self.deadletter, &mut self.stats).await;
+    }
+}
+
+/// Whether a point is older than `max_age` seconds at `now`.
+fn is_stale(point: &HomiePoint, max_age: Option<u64>, now: i64) -> bool {
+    max_age.is_some_and(|max_age| now - point.timestamp > (max_age as i64))
+}
+
+/// The files in a directory of rotated segments, skipping hidden ones, by their first point,
+/// as rotated names don't sort the same way everywhere.
+fn segments_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if entry.file_type()?.is_file() && !hidden {
+            let first = first_timestamp(&entry.path())?;
+            segments.push((first, entry.path()));
+        }
+    }
+    segments.sort();
+    Ok(segments.into_iter().map(|(_, path)| path).collect())
+}
+
+/// The timestamp of the first point in a segment, which is its oldest as segments are
+/// written in order.
+fn first_timestamp(path: &Path) -> io::Result<Option<i64>> {
+    for line in open_lines(path)?.lines() {
+        let line = line?;
+        if let Some(point) = entry(&line).and_then(parse_line_protocol) {
+            return Ok(Some(point.timestamp));
+        }
+    }
+    Ok(None)
+}
+
+/// The lines of a file, decompressing it if it is gzipped.
+fn open_lines(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// The line protocol in a line, the last column of a deadletter line, or `None` for a blank
+/// line or a comment.
+fn entry(line: &str) -> Option<&str> {
+    let line = line.rsplit('\t').next().unwrap_or_default().trim();
+    (!line.is_empty() && !line.starts_with('#')).then_some(line)
+}
+
+async fn write_chunks(
+    mut points: Vec<HomiePoint>,
+    sink: &mut Sink,
+    deadletter: &mut Deadletter,
+    stats: &mut ReplayStats
+) {
+    while !points.is_empty() {
+        let rest = points.split_off(points.len().min(REPLAY_CHUNK));
+        let total = points.len() as u64;
+        let failed = sink.write_batch(points).await;
+        stats.written += total - (failed.len() as u64);
+        stats.failed += failed.len() as u64;
+        for (point, e) in failed {
+            deadletter.record(&point, &e.to_string());
+        }
+        points = rest;
+    }
+}
+
+/// Parse one line of influx line protocol. Timestamps are taken as seconds, or scaled down
+/// when they are clearly in milli-, micro- or nanoseconds; a missing one means now.
+pub fn parse_line_protocol(line: &str) -> Option<HomiePoint> {
+    let sections = split_unescaped(line, ' ');
+    let (series, fields, timestamp) = match sections.as_slice() {
+        [series, fields] => (*series, *fields, None),
+        [series, fields, timestamp] => (*series, *fields, Some(*timestamp)),
+        _ => {
+            return None;
+        }
+    };
+
+    let mut series = split_unescaped(series, ',').into_iter();
+    let measurement = unescape(series.next().filter(|m| !m.is_empty())?);
+    let mut tags = Vec::new();
+    for tag in series {
+        let [key, value] = split_unescaped(tag, '=')[..] else {
+            return None;
+        };
+        tags.push((unescape(key), unescape(value)));
+    }
+
+    let mut parsed_fields = Vec::new();
+    for field in split_unescaped(fields, ',') {
+        let (key, value) = field.split_once('=')?;
+        parsed_fields.push((unescape(key), parse_field(value)?));
+    }
+    if parsed_fields.is_empty() {
+        return None;
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) => {
+            let timestamp: i64 = timestamp.parse().ok()?;
+            match timestamp.unsigned_abs() {
+                t if t >= 100_000_000_000_000_000 => timestamp / 1_000_000_000,
+                t if t >= 100_000_000_000_000 => timestamp / 1_000_000,
+                t if t >= 100_000_000_000 => timestamp / 1_000,
+                _ => timestamp,
+            }
+        }
+        None => Utc::now().timestamp(),
+    };
+
+    Some(HomiePoint { measurement, fields: parsed_fields, tags, timestamp })
+}
+
+fn parse_field(value: &str) -> Option<FieldValue> {
+    if let Some(text) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return Some(FieldValue::Text(text.replace("\\\"", "\"").replace("\\\\", "\\")));
+    }
+    if let Some(i) = value.strip_suffix('i') {
+        return i.parse().ok().map(FieldValue::Integer);
+    }
+    if let Some(u) = value.strip_suffix('u') {
+        return u.parse().ok().map(FieldValue::Unsigned);
+    }
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" => Some(FieldValue::Boolean(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => Some(FieldValue::Boolean(false)),
+        _ => value.parse().ok().map(FieldValue::Float),
+    }
+}
+
+/// Split at each `sep` that isn't backslash-escaped or inside a quoted string.
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => {
+                escaped = false;
+            }
+            '\\' => {
+                escaped = true;
+            }
+            '"' => {
+                quoted = !quoted;
+            }
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Undo the line-protocol escaping of measurements, tag keys and values, and field keys.
+fn unescape(s: &str) -> String {
+    s.replace("\\,", ",").replace("\\=", "=").replace("\\ ", " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::sink::{ telegraf_client, TelTransport };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("homie-replay-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_line_protocol() {
+        let line = concat!(
+            r"HomieMetric,device_id_tag=thermostat,node_id_tag=zone\ 1 ",
+            r#"value=21.5,count=3i,seq=7u,on=t,mode="heat" 1700000000"#
+        );
+        let point = parse_line_protocol(line).unwrap();
+        assert_eq!(point.measurement, "HomieMetric");
+        assert_eq!(point.tag("node_id_tag"), Some("zone 1"));
+        assert_eq!(point.fields, vec![
+            ("value".to_string(), FieldValue::Float(21.5)),
+            ("count".to_string(), FieldValue::Integer(3)),
+            ("seq".to_string(), FieldValue::Unsigned(7)),
+            ("on".to_string(), FieldValue::Boolean(true)),
+            ("mode".to_string(), FieldValue::Text("heat".to_string()))
+        ]);
+        assert_eq!(point.timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn booleans_stay_booleans() {
+        let point = parse_line_protocol("m on=false 1700000000").unwrap();
+        assert_eq!(point.fields[0].1, FieldValue::Boolean(false));
+        assert_eq!(point.to_line_protocol(None), "m on=false 1700000000");
+    }
+
+    #[test]
+    fn scales_down_finer_timestamps() {
+        for timestamp in ["1700000000", "1700000000000", "1700000000000000", "1700000000000000000"] {
+            let point = parse_line_protocol(&format!("m value=1 {}", timestamp)).unwrap();
+            assert_eq!(point.timestamp, 1_700_000_000);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse_line_protocol("m").is_none());
+        assert!(parse_line_protocol("m value=abc 1700000000").is_none());
+        assert!(parse_line_protocol(",tag=x value=1 1700000000").is_none());
+        assert!(parse_line_protocol("m value=1 soon").is_none());
+    }
+
+    #[test]
+    fn entry_takes_the_last_deadletter_column() {
+        assert_eq!(entry("1700000000\ttimeout\tm value=1 1700000000"), Some("m value=1 1700000000"));
+        assert_eq!(entry("# comment"), None);
+        assert_eq!(entry("   "), None);
+    }
+
+    #[test]
+    fn drops_stale_points_only_with_a_max_age() {
+        let point = parse_line_protocol("m value=1 1000").unwrap();
+        assert!(!is_stale(&point, None, 10_000));
+        assert!(!is_stale(&point, Some(60), 1_060));
+        assert!(is_stale(&point, Some(60), 1_061));
+    }
+
+    #[test]
+    fn streams_plain_and_gzipped_segments_oldest_first() {
+        let dir = temp_dir("segments");
+        fs::write(dir.join("b.lp"), "m value=2 2000\nm value=3 3000\n").unwrap();
+        let mut gz = GzEncoder::new(File::create(dir.join("a.lp.gz")).unwrap(), Compression::default());
+        gz.write_all(b"# rotated\nm value=4 4000\n").unwrap();
+        gz.finish().unwrap();
+        fs::write(dir.join(".hidden"), "m value=0 0\n").unwrap();
+
+        let segments = segments_in(&dir).unwrap();
+        assert_eq!(segments, vec![dir.join("b.lp"), dir.join("a.lp.gz")]);
+        let lines: Vec<String> = open_lines(&segments[1]).unwrap().lines().map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["# rotated", "m value=4 4000"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// How long replaying points a second apart for two seconds takes at `speed`.
+    async fn replay_time(speed: f64) -> Duration {
+        let dir = temp_dir(&format!("speed-{}", speed));
+        let path = dir.join("points.lp");
+        fs::write(&path, "m value=1 1700000000\nm value=2 1700000001\nm value=3 1700000002\n").unwrap();
+        let telegraf = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let endpoint = telegraf.local_addr().unwrap().to_string();
+        let mut sink = Sink::Telegraf {
+            client: telegraf_client(TelTransport::Udp, &endpoint).unwrap(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            float_precision: None,
+            max_udp_bytes: usize::MAX,
+            tcp_fallback: None,
+        };
+        let mut deadletter = Deadletter::open(None).unwrap();
+
+        let start = Instant::now();
+        let stats = replay(&path, &mut sink, &mut deadletter, speed, None).await.unwrap();
+        let elapsed = start.elapsed();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(stats.written, 3);
+        elapsed
+    }
+
+    #[tokio::test]
+    async fn replay_timing_scales_with_the_speed() {
+        let at_20 = replay_time(20.0).await;
+        assert!(at_20 >= Duration::from_millis(100) && at_20 < Duration::from_millis(200), "{:?}", at_20);
+        let at_10 = replay_time(10.0).await;
+        assert!(at_10 >= Duration::from_millis(200), "{:?}", at_10);
+        let unpaced = replay_time(0.0).await;
+        assert!(unpaced < Duration::from_millis(100), "{:?}", unpaced);
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::cache::{ series_cache, SeriesCache };
+use crate::point::{ FieldValue, HomiePoint, MEASUREMENT };
+
+/// Re-emits the last point of every series that saw no update during an interval, so push
+/// sinks get a steady cadence through quiet periods. Re-emitted points carry `resampled=1i`.
+pub struct Resampler {
+    interval: Duration,
+    last_tick: Instant,
+    // last point per series, and whether it was updated since the last tick
+    series: SeriesCache<(HomiePoint, bool)>,
+}
+
+impl Resampler {
+    pub fn new(interval: Duration, capacity: usize, now: Instant) -> Resampler {
+        Resampler { interval, last_tick: now, series: series_cache(capacity) }
+    }
+
+    /// Remember the latest value of each device series in `points`.
+    pub fn record(&mut self, points: &[HomiePoint]) {
+        for point in points.iter().filter(|point| point.measurement == MEASUREMENT) {
+            self.series.put(series_key(point), (point.clone(), true));
+        }
+    }
+
+    /// Once per interval, the last points of the series that weren't updated, stamped `timestamp`.
+    pub fn due(&mut self, now: Instant, timestamp: i64) -> Vec<HomiePoint> {
+        if now.duration_since(self.last_tick) < self.interval {
+            return Vec::new();
+        }
+        self.last_tick = now;
+
+        let mut points = Vec::new();
+        for (_, (point, updated)) in self.series.iter_mut() {
+            if !*updated {
+                let mut point = point.clone();
+                point.timestamp = timestamp;
+                point.fields.push(("resampled".to_string(), FieldValue::Integer(1)));
+                points.push(point);
+            }
+            *updated = false;
+        }
+        points
+    }
+}
+
+/// Identifies a series by measurement, tags and field names.
+fn series_key(point: &HomiePoint) -> String {
+    let mut key = point.measurement.clone();
+    for (name, value) in &point.tags {
+        key.push_str(&format!(",{}={}", name, value));
+    }
+    for (name, _) in &point.fields {
+        key.push_str(&format!(" {}", name));
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::VALUE_FIELD;
+
+    const INTERVAL: Duration = Duration::from_secs(60);
+
+    fn point(value: f64) -> HomiePoint {
+        HomiePoint::new(value, "thermostat", "zone", "temperature", 1_000)
+    }
+
+    #[test]
+    fn quiet_series_is_re_emitted_once_per_interval() {
+        let start = Instant::now();
+        let mut resampler = Resampler::new(INTERVAL, 10, start);
+        resampler.record(&[point(21.5)]);
+
+        assert!(resampler.due(start + Duration::from_secs(30), 1_030).is_empty());
+        // updated during the first interval, so nothing to fill in yet
+        assert!(resampler.due(start + INTERVAL, 1_060).is_empty());
+
+        let points = resampler.due(start + 2 * INTERVAL, 1_120);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].timestamp, 1_120);
+        assert_eq!(points[0].fields[0], (VALUE_FIELD.to_string(), FieldValue::Float(21.5)));
+        assert_eq!(points[0].fields[1], ("resampled".to_string(), FieldValue::Integer(1)));
+    }
+
+    #[test]
+    fn updated_series_is_not_re_emitted() {
+        let start = Instant::now();
+        let mut resampler = Resampler::new(INTERVAL, 10, start);
+        resampler.record(&[point(21.5)]);
+        resampler.due(start + INTERVAL, 1_060);
+
+        resampler.record(&[point(22.0)]);
+        assert!(resampler.due(start + 2 * INTERVAL, 1_120).is_empty());
+        let points = resampler.due(start + 3 * INTERVAL, 1_180);
+        assert_eq!(points[0].fields[0], (VALUE_FIELD.to_string(), FieldValue::Float(22.0)));
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{ Deserialize, Serialize };
+
+use crate::cache::{ series_cache, SeriesCache };
+
+/// Fixed decimation of a high-frequency property.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    /// keep every Nth update
+    Every(u64),
+    /// keep the first update in each bucket of this many seconds
+    IntervalSecs(u64),
+}
+
+/// Per-series decimation state: an update count or the last kept time bucket.
+#[derive(Debug)]
+pub struct Sampler {
+    series: SeriesCache<i64>,
+}
+
+impl Sampler {
+    pub fn new(capacity: usize) -> Sampler {
+        Sampler { series: series_cache(capacity) }
+    }
+
+    /// Whether the update of the series at `key` arriving at `timestamp` is kept.
+    pub fn keep(&mut self, key: &str, sampling: &Sampling, timestamp: i64) -> bool {
+        match sampling {
+            Sampling::Every(n) => {
+                let count = self.series.get_or_insert_mut(key.to_string(), || 0);
+                *count += 1;
+                *count % ((*n).max(1) as i64) == 0
+            }
+            Sampling::IntervalSecs(secs) => {
+                let bucket = timestamp.div_euclid((*secs).max(1) as i64);
+                if self.series.get(key) == Some(&bucket) {
+                    false
+                } else {
+                    self.series.put(key.to_string(), bucket);
+                    true
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_keeps_each_nth_update() {
+        let mut sampler = Sampler::new(16);
+        let kept: Vec<bool> = (0..6).map(|t| sampler.keep("t/zone/power", &Sampling::Every(3), t)).collect();
+        assert_eq!(kept, vec![false, false, true, false, false, true]);
+        // every 0 keeps everything rather than dividing by zero
+        assert!(sampler.keep("t/zone/other", &Sampling::Every(0), 0));
+    }
+
+    #[test]
+    fn interval_keeps_the_first_update_per_bucket() {
+        let mut sampler = Sampler::new(16);
+        let interval = Sampling::IntervalSecs(10);
+        let kept: Vec<bool> = [100, 105, 109, 110, 125]
+            .iter()
+            .map(|t| sampler.keep("t/zone/power", &interval, *t))
+            .collect();
+        assert_eq!(kept, vec![true, false, false, true, true]);
+    }
+
+    #[test]
+    fn series_are_sampled_apart() {
+        let mut sampler = Sampler::new(16);
+        let interval = Sampling::IntervalSecs(10);
+        assert!(sampler.keep("t/zone/power", &interval, 100));
+        assert!(sampler.keep("t/zone/current", &interval, 100));
+    }
+
+    #[test]
+    fn parses_from_config() {
+        let sampling: Sampling = toml::from_str("interval_secs = 30").unwrap();
+        assert_eq!(sampling, Sampling::IntervalSecs(30));
+    }
+}
@@ -0,0 +1,63 @@
+use serde::Serialize;
+
+use crate::point::HomiePoint;
+
+/// How characters line protocol can't carry as-is are handled in measurement names, tag keys
+/// and values, and field keys.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sanitize {
+    /// backslash-escape spaces, commas and equals signs when serializing
+    Escape,
+    /// replace spaces, commas and equals signs with underscores
+    Replace,
+}
+
+impl Sanitize {
+    /// Clean the names and tag values of `point`. Control characters can't be escaped in line
+    /// protocol, a newline would end the line, so they are replaced either way.
+    pub fn apply(&self, mut point: HomiePoint) -> HomiePoint {
+        let replace_special = *self == Sanitize::Replace;
+        point.measurement = clean(&point.measurement, replace_special);
+        for (key, value) in &mut point.tags {
+            *key = clean(key, replace_special);
+            *value = clean(value, replace_special);
+        }
+        for (key, _) in &mut point.fields {
+            *key = clean(key, replace_special);
+        }
+        point
+    }
+}
+
+fn clean(s: &str, replace_special: bool) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_control() || (replace_special && matches!(c, ' ' | ',' | '=')) { '_' } else { c }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point() -> HomiePoint {
+        HomiePoint::new(21.5, "living room", "zone,1", "set=point\n", 1_000)
+    }
+
+    #[test]
+    fn escape_only_replaces_control_characters() {
+        let point = Sanitize::Escape.apply(point());
+        assert_eq!(point.tag("device_id_tag"), Some("living room"));
+        assert_eq!(point.tag("property_id_tag"), Some("set=point_"));
+    }
+
+    #[test]
+    fn replace_also_replaces_special_characters() {
+        let point = Sanitize::Replace.apply(point());
+        assert_eq!(point.tag("device_id_tag"), Some("living_room"));
+        assert_eq!(point.tag("node_id_tag"), Some("zone_1"));
+        assert_eq!(point.tag("property_id_tag"), Some("set_point_"));
+    }
+}
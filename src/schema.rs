@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+use crate::point::{ HomiePoint, MEASUREMENT };
+
+/// Where the Homie ids of a device value go: all in tags, or one of them as the measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Schema {
+    /// one HomieMetric measurement, device/node/property as tags
+    Flat,
+    /// the property id as the measurement, device/node as tags
+    PropertyMeasurement,
+    /// the node id as the measurement, device/property as tags
+    NodeMeasurement,
+}
+
+impl Schema {
+    /// Lay out a device value point; annotations, and grouped points lacking the id the
+    /// measurement would come from, are left as they are.
+    pub fn apply(&self, mut point: HomiePoint) -> HomiePoint {
+        let tag = match self {
+            Schema::Flat => {
+                return point;
+            }
+            Schema::PropertyMeasurement => "property_id_tag",
+            Schema::NodeMeasurement => "node_id_tag",
+        };
+        if point.measurement != MEASUREMENT {
+            return point;
+        }
+        if let Some(index) = point.tags.iter().position(|(key, _)| key == tag) {
+            let (_, id) = point.tags.remove(index);
+            point.measurement = id;
+        }
+        point
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point() -> HomiePoint {
+        HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_000)
+    }
+
+    #[test]
+    fn flat_keeps_the_ids_in_tags() {
+        assert_eq!(Schema::Flat.apply(point()).to_line_protocol(None), point().to_line_protocol(None));
+    }
+
+    #[test]
+    fn property_or_node_id_becomes_the_measurement() {
+        let by_property = Schema::PropertyMeasurement.apply(point());
+        assert_eq!(by_property.measurement, "temperature");
+        assert_eq!(by_property.tag("property_id_tag"), None);
+        assert_eq!(by_property.tag("node_id_tag"), Some("zone"));
+
+        let by_node = Schema::NodeMeasurement.apply(point());
+        assert_eq!(by_node.measurement, "zone");
+        assert_eq!(by_node.tag("node_id_tag"), None);
+        assert_eq!(by_node.tag("property_id_tag"), Some("temperature"));
+    }
+
+    #[test]
+    fn annotations_are_left_alone() {
+        let mut annotation = point();
+        annotation.measurement = "annotation".to_string();
+        let applied = Schema::PropertyMeasurement.apply(annotation.clone());
+        assert_eq!(applied.to_line_protocol(None), annotation.to_line_protocol(None));
+    }
+}
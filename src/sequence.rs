@@ -0,0 +1,60 @@
+use crate::cache::{ series_cache, SeriesCache };
+use crate::point::{ FieldValue, HomiePoint };
+
+/// Numbers each point of a series, 1, 2, 3, ... in the order they are handed to the sink, so
+/// a consumer can spot gaps left by points lost on the way, e.g. over UDP.
+pub struct Sequencer {
+    last: SeriesCache<u64>,
+}
+
+impl Sequencer {
+    pub fn new(capacity: usize) -> Sequencer {
+        Sequencer { last: series_cache(capacity) }
+    }
+
+    /// Add the point's `seq` field. A series is its measurement and tag set.
+    pub fn apply(&mut self, point: &mut HomiePoint) {
+        let mut tags: Vec<String> = point.tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        tags.sort_unstable();
+        let key = format!("{},{}", point.measurement, tags.join(","));
+
+        let seq = self.last.get(&key).copied().unwrap_or(0) + 1;
+        self.last.put(key, seq);
+        point.fields.push(("seq".to_string(), FieldValue::Unsigned(seq)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seq(sequencer: &mut Sequencer, property_id: &str) -> FieldValue {
+        let mut point = HomiePoint::new(21.5, "thermostat", "zone", property_id, 1_000);
+        sequencer.apply(&mut point);
+        point.fields.pop().unwrap().1
+    }
+
+    #[test]
+    fn each_series_is_numbered_on_its_own() {
+        let mut sequencer = Sequencer::new(10);
+        assert_eq!(seq(&mut sequencer, "temperature"), FieldValue::Unsigned(1));
+        assert_eq!(seq(&mut sequencer, "temperature"), FieldValue::Unsigned(2));
+        assert_eq!(seq(&mut sequencer, "humidity"), FieldValue::Unsigned(1));
+        assert_eq!(seq(&mut sequencer, "temperature"), FieldValue::Unsigned(3));
+    }
+
+    #[test]
+    fn tag_order_does_not_split_a_series() {
+        let mut sequencer = Sequencer::new(10);
+        let mut point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_000);
+        sequencer.apply(&mut point);
+
+        let mut reordered = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_000);
+        reordered.tags.reverse();
+        sequencer.apply(&mut reordered);
+        assert_eq!(reordered.fields.last().unwrap().1, FieldValue::Unsigned(2));
+    }
+}
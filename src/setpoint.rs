@@ -0,0 +1,67 @@
+use serde::{ Deserialize, Serialize };
+
+use crate::cache::SeriesCache;
+
+/// A settable target property and the property reporting the state it drives, both given
+/// as `node/property` within the same device.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TargetStatePair {
+    pub target: String,
+    pub state: String,
+}
+
+/// `target - state` for each pair the changed property belongs to, once both sides of the
+/// pair have been seen on `device_id`. Returns the pair and its delta.
+pub fn deltas<'a>(
+    pairs: &'a [TargetStatePair],
+    latest: &mut SeriesCache<f64>,
+    device_id: &str,
+    node_id: &str,
+    property_id: &str
+) -> Vec<(&'a TargetStatePair, f64)> {
+    let changed = format!("{}/{}", node_id, property_id);
+    pairs
+        .iter()
+        .filter(|pair| pair.target == changed || pair.state == changed)
+        .filter_map(|pair| {
+            let target = *latest.get(&format!("{}/{}", device_id, pair.target))?;
+            let state = *latest.get(&format!("{}/{}", device_id, pair.state))?;
+            Some((pair, target - state))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::series_cache;
+
+    fn pairs() -> Vec<TargetStatePair> {
+        vec![TargetStatePair {
+            target: "zone/setpoint".to_string(),
+            state: "zone/temperature".to_string(),
+        }]
+    }
+
+    #[test]
+    fn delta_once_both_sides_are_seen() {
+        let pairs = pairs();
+        let mut latest = series_cache(16);
+        latest.put("thermostat/zone/setpoint".to_string(), 21.0);
+        assert!(deltas(&pairs, &mut latest, "thermostat", "zone", "setpoint").is_empty());
+
+        latest.put("thermostat/zone/temperature".to_string(), 19.5);
+        let found = deltas(&pairs, &mut latest, "thermostat", "zone", "temperature");
+        assert_eq!(found, vec![(&pairs[0], 1.5)]);
+    }
+
+    #[test]
+    fn only_for_the_pair_of_the_changed_property_on_its_device() {
+        let pairs = pairs();
+        let mut latest = series_cache(16);
+        latest.put("thermostat/zone/setpoint".to_string(), 21.0);
+        latest.put("thermostat/zone/temperature".to_string(), 19.5);
+        assert!(deltas(&pairs, &mut latest, "thermostat", "zone", "humidity").is_empty());
+        assert!(deltas(&pairs, &mut latest, "other", "zone", "setpoint").is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use homie_controller::Event;
+use rand::Rng;
+use tokio::time::Instant;
+
+// how often simulated events are handed to the loop
+const TICK: Duration = Duration::from_millis(100);
+
+/// Synthetic `PropertyValueChanged` events at a steady rate, cycling through
+/// `devices * nodes * properties` series, for load testing without a broker.
+#[derive(Debug)]
+pub struct Simulator {
+    rate: u32,
+    devices: usize,
+    nodes: usize,
+    properties: usize,
+    started: Instant,
+    sent: u64,
+}
+
+impl Simulator {
+    pub fn new(rate: u32, devices: usize, nodes: usize, properties: usize) -> Simulator {
+        Simulator {
+            rate,
+            devices: devices.max(1),
+            nodes: nodes.max(1),
+            properties: properties.max(1),
+            started: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Wait for the next tick and return the events due by then.
+    pub async fn tick(&mut self) -> Vec<Event> {
+        tokio::time::sleep(TICK).await;
+        let due = (self.started.elapsed().as_secs_f64() * (self.rate as f64)) as u64;
+        let events = (self.sent..due).map(|n| self.event(n)).collect();
+        self.sent = due.max(self.sent);
+        events
+    }
+
+    /// The `n`th event: one value per series in turn. Every fourth property is a boolean so
+    /// the mapping tables are exercised as well as float parsing.
+    fn event(&self, n: u64) -> Event {
+        let series = (n as usize) % (self.devices * self.nodes * self.properties);
+        let property = series % self.properties;
+        let node = (series / self.properties) % self.nodes;
+        let device = series / (self.properties * self.nodes);
+
+        let mut rng = rand::thread_rng();
+        let value = if property % 4 == 3 {
+            rng.gen_bool(0.5).to_string()
+        } else {
+            format!("{:.2}", rng.gen_range(0.0..100.0))
+        };
+        Event::PropertyValueChanged {
+            device_id: format!("sim-device-{}", device),
+            node_id: format!("node-{}", node),
+            property_id: format!("property-{}", property),
+            value,
+            fresh: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(event: &Event) -> (&str, &str, &str, &str) {
+        match event {
+            Event::PropertyValueChanged { device_id, node_id, property_id, value, .. } =>
+                (device_id, node_id, property_id, value),
+            _ => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn events_cycle_through_every_series() {
+        let simulator = Simulator::new(100, 2, 1, 4);
+        let event = simulator.event(0);
+        assert_eq!(parts(&event).0, "sim-device-0");
+        assert_eq!(parts(&event).2, "property-0");
+        assert!(parts(&event).3.parse::<f64>().is_ok());
+
+        let event = simulator.event(7);
+        assert_eq!(parts(&event).0, "sim-device-1");
+        assert_eq!(parts(&event).2, "property-3");
+        assert!(parts(&event).3.parse::<bool>().is_ok());
+
+        // back to the first series
+        assert_eq!(parts(&simulator.event(8)).0, "sim-device-0");
+    }
+
+    #[tokio::test]
+    async fn tick_hands_out_the_events_due_at_the_rate() {
+        let mut simulator = Simulator::new(100, 1, 1, 1);
+        // a 100 ms tick at 100 per second
+        assert!(simulator.tick().await.len() >= 10);
+        let sent = simulator.sent;
+        simulator.tick().await;
+        assert!(simulator.sent >= sent + 10);
+    }
+}
@@ -0,0 +1,842 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::{ Hash, Hasher };
+use std::net::UdpSocket;
+use std::sync::Arc;
+
+use serde::Serialize;
+use telegraf::Client;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::backoff::Backoff;
+use crate::error::BridgeError;
+use crate::influx_native::NativeInfluxClient;
+use crate::kafka::KafkaClient;
+use crate::net::host_port;
+use crate::point::{ HomiePoint, MEASUREMENT };
+use crate::template::MeasurementTemplate;
+use crate::victoria::VictoriaMetricsClient;
+
+/// Payload format sent to the telegraf socket, matching its `data_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TelFormat {
+    /// influx line protocol
+    Line,
+    /// telegraf's JSON metric shape, one object per datagram
+    Json,
+}
+
+/// Socket type of the telegraf `socket_listener`, the scheme of its `service_address`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TelTransport {
+    Udp,
+    Tcp,
+}
+
+impl fmt::Display for TelTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelTransport::Udp => write!(f, "udp"),
+            TelTransport::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// A telegraf client for `transport://endpoint`. A tcp endpoint is connected right away, so
+/// a wrong port fails here rather than on the first write.
+pub fn telegraf_client(transport: TelTransport, endpoint: &str) -> Result<Client, BridgeError> {
+    let url = format!("{}://{}", transport, endpoint);
+    Client::new(&url).map_err(|e| BridgeError::Telegraf(url, e.to_string()))
+}
+
+/// How points are spread over several telegraf sockets.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Balance {
+    /// each point to the next socket in turn
+    RoundRobin,
+    /// all of a device's points to the same socket
+    Device,
+}
+
+/// Where converted points are written, selected by the push method.
+pub enum Sink {
+    Telegraf {
+        client: Client,
+        host: String,
+        port: u16,
+        float_precision: Option<usize>,
+        /// largest point sent over UDP without a warning
+        max_udp_bytes: usize,
+        /// TCP client larger points are sent with instead
+        tcp_fallback: Option<Client>,
+    },
+    TelegrafJson {
+        socket: UdpSocket,
+        float_precision: Option<usize>,
+        max_udp_bytes: usize,
+    },
+    /// several telegraf sockets, each a `Telegraf` or `TelegrafJson` sink
+    TelegrafBalanced {
+        sockets: Vec<Sink>,
+        balance: Balance,
+        next: usize,
+    },
+    Influx {
+        client: Box<influxdb_rs::Client>,
+        /// clients for measurements routed to another bucket
+        routes: HashMap<String, influxdb_rs::Client>,
+        retries: u32,
+        backoff: Backoff,
+        measurement: Option<MeasurementTemplate>,
+    },
+    InfluxNative {
+        client: Arc<NativeInfluxClient>,
+        /// bucket per measurement, others go to the client's bucket
+        buckets: HashMap<String, String>,
+        retries: u32,
+        backoff: Backoff,
+        measurement: Option<MeasurementTemplate>,
+        /// most line-protocol bytes per request, a batch is split to stay under it
+        max_batch_bytes: Option<usize>,
+        /// permits for requests in flight at once, the rest wait for one
+        in_flight: Arc<Semaphore>,
+    },
+    VictoriaMetrics {
+        client: VictoriaMetricsClient,
+        retries: u32,
+        backoff: Backoff,
+    },
+    Kafka {
+        client: KafkaClient,
+        retries: u32,
+        backoff: Backoff,
+    },
+}
+
+impl Sink {
+    /// The push method name this sink is selected by.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sink::Telegraf { .. } | Sink::TelegrafJson { .. } | Sink::TelegrafBalanced { .. } => {
+                "telegraf"
+            }
+            Sink::Influx { .. } | Sink::InfluxNative { .. } => "influx",
+            Sink::VictoriaMetrics { .. } => "victoriametrics",
+            Sink::Kafka { .. } => "kafka",
+        }
+    }
+
+    /// Points the sink accepted but then dropped, as reported by partial writes.
+    pub fn rejected(&self) -> u64 {
+        match self {
+            Sink::InfluxNative { client, .. } => client.rejected(),
+            _ => 0,
+        }
+    }
+
+    /// Write a batch, returning each point that couldn't be written with its error. The native
+    /// influx client sends it in as few requests per bucket as the byte limit allows, as many
+    /// at once as `in_flight` permits, failing chunk by chunk; the other sinks write point by
+    /// point.
+    pub async fn write_batch(&mut self, points: Vec<HomiePoint>) -> Vec<(HomiePoint, BridgeError)> {
+        let mut failed = Vec::new();
+
+        match self {
+            Sink::InfluxNative {
+                client,
+                buckets,
+                retries,
+                backoff,
+                measurement,
+                max_batch_bytes,
+                in_flight,
+            } => {
+                // group by bucket, keeping each bucket's points in arrival order
+                let mut by_bucket: Vec<(Option<String>, Vec<HomiePoint>)> = Vec::new();
+                for mut point in points {
+                    point.measurement = influx_measurement(measurement, &point);
+                    let bucket = buckets.get(&point.measurement).cloned();
+                    match by_bucket.iter_mut().find(|(other, _)| *other == bucket) {
+                        Some((_, group)) => group.push(point),
+                        None => by_bucket.push((bucket, vec![point])),
+                    }
+                }
+
+                // each task's points stay here too, in case the task never returns them
+                let mut writes = JoinSet::new();
+                let mut unfinished: Vec<Option<Arc<[HomiePoint]>>> = Vec::new();
+                for (bucket, group) in &by_bucket {
+                    for chunk in client.chunks(group, *max_batch_bytes) {
+                        let chunk: Arc<[HomiePoint]> = chunk.into();
+                        let index = unfinished.len();
+                        unfinished.push(Some(chunk.clone()));
+                        let bucket = bucket.clone();
+                        let client = client.clone();
+                        let in_flight = in_flight.clone();
+                        let (retries, backoff) = (*retries, *backoff);
+                        writes.spawn(async move {
+                            // held until the write and its retries are done
+                            let _permit = in_flight.acquire_owned().await;
+                            let (client, points, bucket) = (&client, &chunk[..], bucket.as_deref());
+                            let res = retry("influxdb", retries, &backoff, move || async move {
+                                client.write(points, bucket).await
+                            }).await;
+                            (index, res.map_err(|e| e.to_string()))
+                        });
+                    }
+                }
+
+                failed.extend(join_writes(writes, unfinished).await);
+            }
+            _ => {
+                for point in points {
+                    if let Err(e) = self.write(&point).await {
+                        failed.push((point, e));
+                    }
+                }
+            }
+        }
+        failed
+    }
+
+    /// Write one point to a telegraf socket; with several, to the one `balance` picks, or
+    /// the next that takes it when that one fails.
+    fn write_socket(&mut self, point: &HomiePoint) -> Result<(), BridgeError> {
+        match self {
+            Sink::Telegraf { client, host, port, float_precision, max_udp_bytes, tcp_fallback } => {
+                // measured on what is sent, the telegraf crate serializes on its own
+                let line = point.to_telegraf_line(*float_precision);
+                let size = line.len();
+                let client = if size > *max_udp_bytes {
+                    match tcp_fallback {
+                        Some(tcp) => {
+                            debug!("telegraf: {} byte point over udp limit, sending over tcp", size);
+                            tcp
+                        }
+                        None => {
+                            warn!(
+                                "telegraf: {} byte point exceeds {} bytes and may be truncated or dropped over udp: {:?}",
+                                size,
+                                max_udp_bytes,
+                                point
+                            );
+                            client
+                        }
+                    }
+                } else {
+                    client
+                };
+                match client.write_to_conn(line.as_bytes()) {
+                    Ok(_val) => {
+                        trace!("writing point: {:?}", point);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        // e.g. a refused port, reported back on a later udp send
+                        let e = BridgeError::Telegraf(host_port(host, *port), e.to_string());
+                        error!("failed to write point: {}", e);
+                        Err(BridgeError::Write("telegraf", e.to_string()))
+                    }
+                }
+            }
+            Sink::TelegrafJson { socket, float_precision, max_udp_bytes } => {
+                let payload = point.to_telegraf_json(*float_precision).to_string();
+                if payload.len() > *max_udp_bytes {
+                    warn!(
+                        "telegraf: {} byte point exceeds {} bytes and may be truncated or dropped over udp: {}",
+                        payload.len(),
+                        max_udp_bytes,
+                        payload
+                    );
+                }
+                match socket.send(payload.as_bytes()) {
+                    Ok(_) => {
+                        trace!("writing point: {}", payload);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("failed to write point, error writing: {}", e);
+                        Err(BridgeError::Write("telegraf", e.to_string()))
+                    }
+                }
+            }
+            Sink::TelegrafBalanced { sockets, balance, next } => {
+                let first = match balance {
+                    Balance::RoundRobin => {
+                        let first = *next % sockets.len();
+                        *next = next.wrapping_add(1);
+                        first
+                    }
+                    Balance::Device => {
+                        let mut hasher = DefaultHasher::new();
+                        point.tag("device_id_tag").hash(&mut hasher);
+                        (hasher.finish() as usize) % sockets.len()
+                    }
+                };
+                let mut failed = None;
+                for i in (first..sockets.len()).chain(0..first) {
+                    match sockets[i].write_socket(point) {
+                        Ok(()) => {
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            warn!("telegraf: socket {} failed, trying the next", i);
+                            failed = Some(e);
+                        }
+                    }
+                }
+                Err(failed.unwrap_or(BridgeError::Write("telegraf", "no sockets".to_string())))
+            }
+            _ => unreachable!("not a telegraf sink"),
+        }
+    }
+
+    /// Write one point, returning the final error once retries are exhausted.
+    pub async fn write(&mut self, point: &HomiePoint) -> Result<(), BridgeError> {
+        match self {
+            Sink::Telegraf { .. } | Sink::TelegrafJson { .. } | Sink::TelegrafBalanced { .. } => {
+                self.write_socket(point)
+            }
+            Sink::Influx { client, routes, retries, backoff, measurement } => {
+                let measurement = &influx_measurement(measurement, point);
+                let client = routes.get(measurement).unwrap_or(&*client);
+                let res = retry("influxdb", *retries, backoff, move || async move {
+                    let influx_point = point.to_influx_as(measurement);
+                    info!("influx: attempting to write point: [{:?}]", &influx_point);
+                    client
+                        .write_point(influx_point, Some(influxdb_rs::Precision::Seconds), None).await
+                        .map(|_| ())
+                }).await;
+                match res {
+                    Ok(_) => {
+                        info!("influxdb: wrote point to influx db");
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("influxdb: failed to write point to influx db: {}", e);
+                        Err(BridgeError::Write("influx", e.to_string()))
+                    }
+                }
+            }
+            Sink::InfluxNative { client, buckets, retries, backoff, measurement, .. } => {
+                let client = &*client;
+                let mut point = point.clone();
+                point.measurement = influx_measurement(measurement, &point);
+                let point = &point;
+                let bucket = buckets.get(&point.measurement).map(String::as_str);
+                let res = retry("influxdb", *retries, backoff, move || async move {
+                    trace!("influx: attempting to write point: [{:?}]", point);
+                    client.write(std::slice::from_ref(point), bucket).await
+                }).await;
+                match res {
+                    Ok(_) => {
+                        info!("influxdb: wrote point to influx db");
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("influxdb: failed to write point to influx db: {}", e);
+                        Err(BridgeError::Write("influx", e.to_string()))
+                    }
+                }
+            }
+            Sink::VictoriaMetrics { client, retries, backoff } => {
+                let client = &*client;
+                let res = retry("victoriametrics", *retries, backoff, move || async move {
+                    client.write(std::slice::from_ref(point)).await
+                }).await;
+                match res {
+                    Ok(_) => {
+                        trace!("victoriametrics: wrote point: {:?}", point);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("victoriametrics: failed to write point: {}", e);
+                        Err(BridgeError::Write("victoriametrics", e.to_string()))
+                    }
+                }
+            }
+            Sink::Kafka { client, retries, backoff } => {
+                let client = &*client;
+                let res = retry("kafka", *retries, backoff, move || async move {
+                    client.write(point).await
+                }).await;
+                match res {
+                    Ok(_) => {
+                        trace!("kafka: produced point to {}: {:?}", client.topic(), point);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("kafka: failed to produce point: {}", e);
+                        Err(BridgeError::Write("kafka", e.to_string()))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Measurement a point is written to Influx under; only device values are renamed by the
+/// template, annotations keep their own measurement.
+fn influx_measurement(template: &Option<MeasurementTemplate>, point: &HomiePoint) -> String {
+    match template {
+        Some(template) if point.measurement == MEASUREMENT => template.render(point),
+        _ => point.measurement.clone(),
+    }
+}
+
+/// Run `write` until it succeeds or has failed `retries` more times. Each attempt must write
+/// the same point(s) so a write that landed before its error is overwritten, not duplicated.
+async fn retry<F, Fut, E>(
+    sink: &str,
+    retries: u32,
+    backoff: &Backoff,
+    mut write: F
+) -> Result<(), E>
+    where F: FnMut() -> Fut, Fut: Future<Output = Result<(), E>>, E: fmt::Display
+{
+    let mut attempt = 0;
+    loop {
+        match write().await {
+            Ok(()) => {
+                return Ok(());
+            }
+            Err(e) if attempt < retries => {
+                let delay = backoff.delay(attempt);
+                attempt += 1;
+                warn!(
+                    "{}: write failed (attempt {}/{}), retrying in {:?}: {}",
+                    sink,
+                    attempt,
+                    retries,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Wait for the write tasks, each returning the index of its points in `unfinished` and how
+/// the write went, and return the points that failed. The points of a task that panicked or
+/// was cancelled, still in `unfinished`, fail too.
+async fn join_writes(
+    mut writes: JoinSet<(usize, Result<(), String>)>,
+    mut unfinished: Vec<Option<Arc<[HomiePoint]>>>
+) -> Vec<(HomiePoint, BridgeError)> {
+    let mut failed = Vec::new();
+    let mut lost = None;
+    while let Some(written) = writes.join_next().await {
+        match written {
+            Ok((index, res)) => {
+                let Some(chunk) = unfinished[index].take() else {
+                    continue;
+                };
+                match res {
+                    Ok(()) => {
+                        info!("influxdb: wrote {} point(s) to influx db", chunk.len());
+                    }
+                    Err(e) => {
+                        error!("influxdb: failed to write {} point(s) to influx db: {}", chunk.len(), e);
+                        for point in chunk.iter() {
+                            failed.push((point.clone(), BridgeError::Write("influx", e.clone())));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("influxdb: write task failed: {}", e);
+                lost = Some(format!("write task failed: {}", e));
+            }
+        }
+    }
+
+    let e = lost.unwrap_or_else(|| "write task failed".to_string());
+    for chunk in unfinished.into_iter().flatten() {
+        for point in chunk.iter() {
+            failed.push((point.clone(), BridgeError::Write("influx", e.clone())));
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::TcpListener;
+    use std::thread::{ self, JoinHandle };
+    use std::time::Duration;
+
+    use super::*;
+    use crate::influx_native::WriteApi;
+    use crate::point::FieldValue;
+
+    /// A server answering one request per connection with the given statuses, in order,
+    /// handing back the request lines and bodies it saw.
+    fn serve(statuses: Vec<&'static str>) -> (String, JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push((request_line.trim_end().to_string(), String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    /// A local udp socket to send to, and its address.
+    fn udp_listener() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let endpoint = socket.local_addr().unwrap().to_string();
+        (socket, endpoint)
+    }
+
+    /// The datagrams waiting on `socket`.
+    fn received(socket: &UdpSocket) -> Vec<String> {
+        let mut datagrams = Vec::new();
+        let mut buf = [0; 65_536];
+        while let Ok(len) = socket.recv(&mut buf) {
+            datagrams.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+        datagrams
+    }
+
+    fn telegraf_sink(endpoint: &str, max_udp_bytes: usize, tcp_fallback: Option<Client>) -> Sink {
+        Sink::Telegraf {
+            client: telegraf_client(TelTransport::Udp, endpoint).unwrap(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            float_precision: None,
+            max_udp_bytes,
+            tcp_fallback,
+        }
+    }
+
+    fn chunk(values: &[f64]) -> Arc<[HomiePoint]> {
+        values
+            .iter()
+            .map(|value| HomiePoint::new(*value, "thermostat", "zone", "temperature", 1_700_000_000))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn points_of_a_failed_write_task_are_not_lost() {
+        let unfinished = vec![Some(chunk(&[1.0])), Some(chunk(&[2.0, 3.0])), Some(chunk(&[4.0]))];
+        let mut writes = JoinSet::new();
+        writes.spawn(async { (0, Ok(())) });
+        writes.spawn(async { (1, Err("timed out".to_string())) });
+        writes.spawn(async {
+            let chunk = 2;
+            if chunk == 2 {
+                panic!("write task panicked");
+            }
+            (chunk, Ok(()))
+        });
+
+        let failed = join_writes(writes, unfinished).await;
+        let value = |point: &HomiePoint| match point.fields[0].1 {
+            FieldValue::Float(value) => value,
+            _ => f64::NAN,
+        };
+        let mut values: Vec<f64> = failed.iter().map(|(point, _)| value(point)).collect();
+        values.sort_by(f64::total_cmp);
+        assert_eq!(values, vec![2.0, 3.0, 4.0]);
+        let panicked = failed.iter().find(|(point, _)| value(point) == 4.0);
+        assert!(panicked.unwrap().1.to_string().contains("write task failed"));
+    }
+
+    #[test]
+    fn unreachable_tcp_telegraf_is_a_friendly_error() {
+        // a port that was just free, so nothing listens on it
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let endpoint = format!("127.0.0.1:{}", port);
+        let e = telegraf_client(TelTransport::Tcp, &endpoint).err().unwrap();
+        let url = format!("tcp://{}", endpoint);
+        assert!(matches!(&e, BridgeError::Telegraf(failed, _) if *failed == url));
+        let message = e.to_string();
+        assert!(message.contains("socket_listener"), "{}", message);
+        assert!(message.contains("--tel-transport"), "{}", message);
+    }
+
+    #[test]
+    fn udp_telegraf_needs_no_listener() {
+        assert!(telegraf_client(TelTransport::Udp, "127.0.0.1:9").is_ok());
+    }
+
+    #[test]
+    fn telegraf_line_is_what_the_client_sends() {
+        let (socket, endpoint) = udp_listener();
+        let point = HomiePoint::new(21.5, "living room", "zone", "temperature", 1_700_000_000)
+            .with_tag("room", "living room");
+        telegraf_client(TelTransport::Udp, &endpoint).unwrap().write_point(&point.to_telegraf(None)).unwrap();
+        assert_eq!(received(&socket), [point.to_telegraf_line(None)]);
+    }
+
+    #[test]
+    fn oversized_udp_point_goes_over_tcp_when_there_is_a_fallback() {
+        let small = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        let large = small.clone().with_tag("description", &"x".repeat(200));
+        let max_udp_bytes = small.to_telegraf_line(None).len();
+
+        let (udp, endpoint) = udp_listener();
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_endpoint = tcp.local_addr().unwrap().to_string();
+        let fallback = telegraf_client(TelTransport::Tcp, &tcp_endpoint).unwrap();
+        let mut sink = telegraf_sink(&endpoint, max_udp_bytes, Some(fallback));
+        sink.write_socket(&small).unwrap();
+        sink.write_socket(&large).unwrap();
+        drop(sink);
+
+        assert_eq!(received(&udp), [small.to_telegraf_line(None)]);
+        let mut over_tcp = String::new();
+        tcp.accept().unwrap().0.read_to_string(&mut over_tcp).unwrap();
+        assert_eq!(over_tcp, large.to_telegraf_line(None));
+
+        // without one it is still sent over udp, with a warning
+        let mut sink = telegraf_sink(&endpoint, max_udp_bytes, None);
+        sink.write_socket(&large).unwrap();
+        assert_eq!(received(&udp), [large.to_telegraf_line(None)]);
+    }
+
+    fn balanced(sockets: Vec<Sink>, balance: Balance) -> Sink {
+        Sink::TelegrafBalanced { sockets, balance, next: 0 }
+    }
+
+    fn device_point(device_id: &str, value: f64) -> HomiePoint {
+        HomiePoint::new(value, device_id, "zone", "temperature", 1_700_000_000)
+    }
+
+    #[test]
+    fn round_robin_alternates_between_sockets() {
+        let (first, first_endpoint) = udp_listener();
+        let (second, second_endpoint) = udp_listener();
+        let mut sink = balanced(
+            vec![
+                telegraf_sink(&first_endpoint, usize::MAX, None),
+                telegraf_sink(&second_endpoint, usize::MAX, None)
+            ],
+            Balance::RoundRobin
+        );
+        let points: Vec<HomiePoint> = (0..4).map(|i| device_point("thermostat", i as f64)).collect();
+        for point in &points {
+            sink.write_socket(point).unwrap();
+        }
+
+        let line = |i: usize| points[i].to_telegraf_line(None);
+        assert_eq!(received(&first), [line(0), line(2)]);
+        assert_eq!(received(&second), [line(1), line(3)]);
+    }
+
+    #[test]
+    fn device_balance_keeps_a_device_on_one_socket() {
+        let (first, first_endpoint) = udp_listener();
+        let (second, second_endpoint) = udp_listener();
+        let mut sink = balanced(
+            vec![
+                telegraf_sink(&first_endpoint, usize::MAX, None),
+                telegraf_sink(&second_endpoint, usize::MAX, None)
+            ],
+            Balance::Device
+        );
+        for device_id in ["thermostat", "heater", "meter", "fan"] {
+            for value in [1.0, 2.0, 3.0] {
+                sink.write_socket(&device_point(device_id, value)).unwrap();
+            }
+        }
+
+        let (first, second) = (received(&first), received(&second));
+        assert_eq!(first.len() + second.len(), 12);
+        for device_id in ["thermostat", "heater", "meter", "fan"] {
+            let tag = format!("device_id_tag={}", device_id);
+            let on_first = first.iter().filter(|line| line.contains(&tag)).count();
+            let on_second = second.iter().filter(|line| line.contains(&tag)).count();
+            assert!(on_first == 3 && on_second == 0 || on_first == 0 && on_second == 3, "{}", device_id);
+        }
+    }
+
+    #[test]
+    fn failed_socket_does_not_stop_writes_to_the_other() {
+        // an unconnected socket, every send fails
+        let broken = Sink::TelegrafJson {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            float_precision: None,
+            max_udp_bytes: usize::MAX,
+        };
+        let (working, endpoint) = udp_listener();
+        let mut sink = balanced(
+            vec![broken, telegraf_sink(&endpoint, usize::MAX, None)],
+            Balance::RoundRobin
+        );
+        for i in 0..4 {
+            sink.write_socket(&device_point("thermostat", i as f64)).unwrap();
+        }
+        assert_eq!(received(&working).len(), 4);
+    }
+
+    fn native_sink(url: &str, buckets: HashMap<String, String>, retries: u32) -> Sink {
+        let client = NativeInfluxClient
+            ::new(url, WriteApi::V2 { org: "home" }, "homie", "t", Duration::from_secs(1), false, None)
+            .unwrap();
+        Sink::InfluxNative {
+            client: Arc::new(client),
+            buckets,
+            retries,
+            backoff: Backoff::new(Duration::from_millis(1), Duration::from_millis(1)),
+            measurement: None,
+            max_batch_bytes: None,
+            in_flight: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    #[tokio::test]
+    async fn retried_batch_resends_the_same_timestamps() {
+        let (url, server) = serve(vec!["500 Internal Server Error", "204 No Content"]);
+        let mut sink = native_sink(&url, HashMap::new(), 1);
+        let points = vec![
+            HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000),
+            HomiePoint::new(40.0, "thermostat", "zone", "humidity", 1_700_000_001)
+        ];
+        assert!(sink.write_batch(points.clone()).await.is_empty());
+
+        let bodies: Vec<String> = server.join().unwrap().into_iter().map(|(_, body)| body).collect();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0], bodies[1]);
+        let lines: Vec<String> = points.iter().map(|point| point.to_line_protocol(None)).collect();
+        assert_eq!(bodies[1], lines.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn mapped_measurement_is_written_to_its_bucket() {
+        let (url, server) = serve(vec!["204 No Content", "204 No Content"]);
+        let buckets = HashMap::from([("heating".to_string(), "short_term".to_string())]);
+        let mut sink = native_sink(&url, buckets, 0);
+        let mut heating = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        heating.measurement = "heating".to_string();
+        let other = HomiePoint::new(40.0, "thermostat", "zone", "humidity", 1_700_000_000);
+        assert!(sink.write_batch(vec![heating.clone(), other.clone()]).await.is_empty());
+
+        let requests = server.join().unwrap();
+        let written_to = |point: &HomiePoint| {
+            let (request_line, _) = requests
+                .iter()
+                .find(|(_, body)| *body == point.to_line_protocol(None))
+                .unwrap();
+            request_line.clone()
+        };
+        assert!(written_to(&heating).contains("bucket=short_term&"), "{}", written_to(&heating));
+        assert!(written_to(&other).contains("bucket=homie&"), "{}", written_to(&other));
+    }
+
+    #[test]
+    fn one_metric_in_both_telegraf_formats() {
+        let mut point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 1_700_000_000);
+        point.fields.push(("state".to_string(), FieldValue::Text("on".to_string())));
+
+        let (listener, endpoint) = udp_listener();
+        telegraf_sink(&endpoint, usize::MAX, None).write_socket(&point).unwrap();
+        let line = received(&listener).remove(0);
+        let (series, rest) = line.split_once(' ').unwrap();
+        let tags = "device_id_tag=thermostat,node_id_tag=zone,property_id_tag=temperature";
+        assert_eq!(series, format!("{},{}", MEASUREMENT, tags));
+        assert_eq!(rest.trim_end(), "state=\"on\",value=21.5 1700000000000000000");
+
+        let (listener, endpoint) = udp_listener();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(&endpoint).unwrap();
+        let mut json = Sink::TelegrafJson { socket, float_precision: None, max_udp_bytes: usize::MAX };
+        json.write_socket(&point).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&received(&listener)[0]).unwrap();
+        assert_eq!(payload["name"], MEASUREMENT);
+        assert_eq!(payload["tags"]["device_id_tag"], "thermostat");
+        assert_eq!(payload["fields"]["value"], 21.5);
+        assert_eq!(payload["fields"]["state"], "on");
+        assert_eq!(payload["timestamp"], 1_700_000_000);
+    }
+
+    #[tokio::test]
+    async fn in_flight_writes_never_exceed_the_limit() {
+        use std::sync::atomic::{ AtomicUsize, Ordering };
+
+        // answers each request after a while, tracking how many it holds at once
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let (active, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let server = {
+            let (active, most) = (active.clone(), most.clone());
+            thread::spawn(move || {
+                let handlers: Vec<JoinHandle<()>> = (0..8)
+                    .map(|_| {
+                        let (stream, _) = listener.accept().unwrap();
+                        let (active, most) = (active.clone(), most.clone());
+                        thread::spawn(move || {
+                            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                            most.fetch_max(now, Ordering::SeqCst);
+                            let mut reader = BufReader::new(stream);
+                            let mut line = String::new();
+                            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                                line.clear();
+                            }
+                            thread::sleep(Duration::from_millis(50));
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            let response = "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+                            reader.get_mut().write_all(response.as_bytes()).unwrap();
+                        })
+                    })
+                    .collect();
+                for handler in handlers {
+                    handler.join().unwrap();
+                }
+            })
+        };
+
+        let mut sink = native_sink(&url, HashMap::new(), 0);
+        if let Sink::InfluxNative { max_batch_bytes, in_flight, .. } = &mut sink {
+            // a request per point
+            *max_batch_bytes = Some(1);
+            *in_flight = Arc::new(Semaphore::new(2));
+        }
+        let points: Vec<HomiePoint> = (0..8)
+            .map(|i| HomiePoint::new(i as f64, "thermostat", "zone", "temperature", 1_700_000_000))
+            .collect();
+        assert!(sink.write_batch(points).await.is_empty());
+
+        server.join().unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+}
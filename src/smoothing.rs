@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::cache::{ series_cache, SeriesCache };
+
+/// How a noisy property is smoothed before it is written.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum Smoothing {
+    /// exponential moving average, `alpha` in (0, 1]; higher follows the raw value closer
+    Ema {
+        alpha: f64,
+    },
+    /// mean of the last `samples` values
+    Window {
+        samples: usize,
+    },
+}
+
+#[derive(Debug)]
+enum SeriesState {
+    Ema(f64),
+    Window(VecDeque<f64>),
+}
+
+/// Per-series smoothing state, keyed by the series path.
+#[derive(Debug)]
+pub struct Smoother {
+    series: SeriesCache<SeriesState>,
+}
+
+impl Smoother {
+    pub fn new(capacity: usize) -> Smoother {
+        Smoother { series: series_cache(capacity) }
+    }
+
+    /// Feed `value` into the series at `key` and return its smoothed value.
+    pub fn apply(&mut self, key: &str, smoothing: &Smoothing, value: f64) -> f64 {
+        match smoothing {
+            Smoothing::Ema { alpha } => {
+                // the first sample seeds the average
+                let state = self.series.get_or_insert_mut(key.to_string(), || SeriesState::Ema(value));
+                match state {
+                    SeriesState::Ema(average) => {
+                        *average = alpha * value + (1.0 - alpha) * *average;
+                        *average
+                    }
+                    _ => {
+                        *state = SeriesState::Ema(value);
+                        value
+                    }
+                }
+            }
+            Smoothing::Window { samples } => {
+                let samples = (*samples).max(1);
+                let state = self.series.get_or_insert_mut(key.to_string(), ||
+                    SeriesState::Window(VecDeque::with_capacity(samples))
+                );
+                if !matches!(state, SeriesState::Window(_)) {
+                    *state = SeriesState::Window(VecDeque::with_capacity(samples));
+                }
+                let SeriesState::Window(window) = state else {
+                    unreachable!();
+                };
+                window.push_back(value);
+                while window.len() > samples {
+                    window.pop_front();
+                }
+                window.iter().sum::<f64>() / (window.len() as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_is_seeded_with_the_first_sample() {
+        let mut smoother = Smoother::new(16);
+        let ema = Smoothing::Ema { alpha: 0.25 };
+        assert_eq!(smoother.apply("t/zone/temperature", &ema, 20.0), 20.0);
+        assert_eq!(smoother.apply("t/zone/temperature", &ema, 24.0), 21.0);
+        // series are smoothed apart
+        assert_eq!(smoother.apply("t/zone/humidity", &ema, 40.0), 40.0);
+    }
+
+    #[test]
+    fn window_is_the_mean_of_the_last_samples() {
+        let mut smoother = Smoother::new(16);
+        let window = Smoothing::Window { samples: 3 };
+        assert_eq!(smoother.apply("t/zone/temperature", &window, 1.0), 1.0);
+        assert_eq!(smoother.apply("t/zone/temperature", &window, 2.0), 1.5);
+        assert_eq!(smoother.apply("t/zone/temperature", &window, 3.0), 2.0);
+        assert_eq!(smoother.apply("t/zone/temperature", &window, 7.0), 4.0);
+    }
+
+    #[test]
+    fn changing_method_starts_the_series_over() {
+        let mut smoother = Smoother::new(16);
+        smoother.apply("t/zone/temperature", &Smoothing::Window { samples: 3 }, 10.0);
+        let ema = Smoothing::Ema { alpha: 0.5 };
+        assert_eq!(smoother.apply("t/zone/temperature", &ema, 30.0), 30.0);
+    }
+
+    #[test]
+    fn parses_from_config() {
+        let ema: Smoothing = toml::from_str("method = \"ema\"\nalpha = 0.3").unwrap();
+        assert_eq!(ema, Smoothing::Ema { alpha: 0.3 });
+        let window: Smoothing = toml::from_str("method = \"window\"\nsamples = 5").unwrap();
+        assert_eq!(window, Smoothing::Window { samples: 5 });
+    }
+}
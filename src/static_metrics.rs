@@ -0,0 +1,61 @@
+use std::collections::{ BTreeMap, HashMap };
+
+use serde::{ Deserialize, Serialize };
+
+use crate::point::HomiePoint;
+
+/// A constant value, such as an install date or coordinates, written as a property of its
+/// own alongside the live ones. Keyed by the full `device/node/property` it is written as.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct StaticMetric {
+    pub value: f64,
+    /// added to the point's device, node and property tags
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// The points for the configured static metrics, stamped `timestamp`. Keys that aren't a
+/// full `device/node/property` are skipped with a warning.
+pub fn points(statics: &HashMap<String, StaticMetric>, timestamp: i64) -> Vec<HomiePoint> {
+    let mut points = Vec::with_capacity(statics.len());
+    for (key, metric) in statics {
+        let ids: Vec<&str> = key.splitn(3, '/').collect();
+        let [device_id, node_id, property_id] = ids[..] else {
+            warn!("skipping static metric {}, expected device/node/property", key);
+            continue;
+        };
+        let mut point = HomiePoint::new(metric.value, device_id, node_id, property_id, timestamp);
+        for (name, value) in &metric.tags {
+            point = point.with_tag(name, value);
+        }
+        points.push(point);
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::{ FieldValue, VALUE_FIELD };
+
+    #[test]
+    fn full_keys_become_tagged_points() {
+        let statics = HashMap::from([
+            (
+                "house/site/latitude".to_string(),
+                StaticMetric {
+                    value: 52.37,
+                    tags: BTreeMap::from([("unit".to_string(), "deg".to_string())]),
+                },
+            ),
+            ("latitude".to_string(), StaticMetric { value: 52.37, tags: BTreeMap::new() }),
+        ]);
+        let points = points(&statics, 1_000);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].tag("device_id_tag"), Some("house"));
+        assert_eq!(points[0].tag("property_id_tag"), Some("latitude"));
+        assert_eq!(points[0].tag("unit"), Some("deg"));
+        assert_eq!(points[0].timestamp, 1_000);
+        assert_eq!(points[0].fields, vec![(VALUE_FIELD.to_string(), FieldValue::Float(52.37))]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::point::{ HomiePoint, MEASUREMENT };
+
+/// Placeholders a measurement template may use and the point tag each resolves to.
+const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("device_id", "device_id_tag"),
+    ("node_id", "node_id_tag"),
+    ("property_id", "property_id_tag"),
+];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    #[error("unknown placeholder {{{0}}}, expected device_id, node_id or property_id")]
+    UnknownPlaceholder(String),
+    #[error("unclosed '{{' in template")]
+    Unclosed,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Tag(&'static str),
+}
+
+/// A measurement name such as `homie_{node_id}`, resolved per point.
+#[derive(Debug, Clone)]
+pub struct MeasurementTemplate {
+    parts: Vec<Part>,
+}
+
+impl FromStr for MeasurementTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or(TemplateError::Unclosed)? + start;
+            let name = &rest[start + 1..end];
+            let tag = PLACEHOLDERS.iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .map(|(_, tag)| *tag)
+                .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_string()))?;
+            parts.push(Part::Tag(tag));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(MeasurementTemplate { parts })
+    }
+}
+
+impl MeasurementTemplate {
+    /// Resolve the template against a point's tags. Characters other than ASCII
+    /// alphanumerics, `_`, `-` and `.` become `_`, and a leading `_` (reserved by Influx) is
+    /// dropped; an empty result falls back to the default measurement.
+    pub fn render(&self, point: &HomiePoint) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Tag(tag) => name.push_str(point.tag(tag).unwrap_or_default()),
+            }
+        }
+
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "_-.".contains(c) { c } else { '_' })
+            .collect();
+        let name = name.trim_start_matches('_');
+        if name.is_empty() {
+            MEASUREMENT.to_string()
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, point: &HomiePoint) -> String {
+        MeasurementTemplate::from_str(template).unwrap().render(point)
+    }
+
+    #[test]
+    fn resolves_placeholders_from_tags() {
+        let point = HomiePoint::new(21.5, "thermostat", "zone", "temperature", 0);
+        assert_eq!(render("homie_{node_id}", &point), "homie_zone");
+        assert_eq!(render("{device_id}.{property_id}", &point), "thermostat.temperature");
+        assert_eq!(render("fixed", &point), "fixed");
+    }
+
+    #[test]
+    fn rejects_unknown_and_unclosed_placeholders() {
+        let unknown = MeasurementTemplate::from_str("homie_{room}").unwrap_err();
+        assert_eq!(unknown, TemplateError::UnknownPlaceholder("room".to_string()));
+        let unclosed = MeasurementTemplate::from_str("homie_{node_id").unwrap_err();
+        assert_eq!(unclosed, TemplateError::Unclosed);
+    }
+
+    #[test]
+    fn sanitizes_the_name() {
+        let point = HomiePoint::new(21.5, "living room", "zone", "temperature", 0);
+        assert_eq!(render("{device_id}/x", &point), "living_room_x");
+        assert_eq!(render("_{node_id}", &point), "zone");
+    }
+
+    #[test]
+    fn empty_name_falls_back_to_the_default_measurement() {
+        let point = HomiePoint::device_metric("uptime", 1.0, "thermostat", 0);
+        assert_eq!(render("{node_id}", &point), MEASUREMENT);
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use rumqttc::{ AsyncClient, Event, MqttOptions, Packet, QoS };
+use tokio::time::Instant;
+
+/// Topics of a Homie device's `$homie` attribute under a base topic of one or two levels.
+/// Only these retained attribute topics are subscribed, never `#`, so discovery doesn't pull
+/// every message on the broker.
+const FILTERS: &[&str] = &["+/+/$homie", "+/+/+/$homie"];
+
+/// Base topics with at least one Homie device, found by listening for `$homie` attributes
+/// for `timeout`. `options` should use a client id of its own.
+pub async fn discover_base_topics(
+    options: MqttOptions,
+    timeout: Duration
+) -> Result<BTreeSet<String>, String> {
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    for filter in FILTERS {
+        client.subscribe(*filter, QoS::AtMostOnce).await.map_err(|e| e.to_string())?;
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut roots = BTreeSet::new();
+    loop {
+        match tokio::time::timeout_at(deadline, event_loop.poll()).await {
+            Err(_elapsed) => {
+                break;
+            }
+            Ok(Err(e)) => {
+                return Err(e.to_string());
+            }
+            Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                let version = String::from_utf8_lossy(&publish.payload);
+                match base_topic(&publish.topic) {
+                    Some(root) if is_homie_version(&version) => {
+                        if roots.insert(root.to_string()) {
+                            debug!("found homie {} device at {}", version, publish.topic);
+                        }
+                    }
+                    _ => trace!("ignoring {} = {}", publish.topic, version),
+                }
+            }
+            Ok(Ok(_)) => {}
+        }
+    }
+    let _ = client.disconnect().await;
+    Ok(roots)
+}
+
+/// The base topic of a `<base>/<device>/$homie` topic.
+fn base_topic(topic: &str) -> Option<&str> {
+    let (base, device) = topic.strip_suffix("/$homie")?.rsplit_once('/')?;
+    if base.is_empty() || device.is_empty() || base.starts_with('$') {
+        return None;
+    }
+    Some(base)
+}
+
+/// Whether a `$homie` payload looks like a spec version, e.g. `4.0.0`.
+fn is_homie_version(version: &str) -> bool {
+    let parts: Vec<&str> = version.trim().split('.').collect();
+    parts.len() >= 2 &&
+        parts.iter().all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_topic_of_homie_attribute() {
+        assert_eq!(base_topic("homie/thermostat/$homie"), Some("homie"));
+        assert_eq!(base_topic("site/floor1/thermostat/$homie"), Some("site/floor1"));
+        assert_eq!(base_topic("thermostat/$homie"), None);
+        assert_eq!(base_topic("$SYS/broker/$homie"), None);
+        assert_eq!(base_topic("homie/thermostat/$name"), None);
+    }
+
+    #[test]
+    fn homie_version_payloads() {
+        assert!(is_homie_version("4.0.0"));
+        assert!(is_homie_version("3.0\n"));
+        assert!(!is_homie_version("4"));
+        assert!(!is_homie_version("4..0"));
+        assert!(!is_homie_version("v4.0"));
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{ BTreeMap, HashSet };
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+/// First index given to an unknown mode, above every built-in table's values.
+const FIRST_INDEX: u64 = 100;
+
+/// Catches mode strings no table knows, which may be new firmware modes: each is logged the
+/// first time a property reports it and, with an index file, given a stable value.
+#[derive(Debug, Default)]
+pub struct UnknownModes {
+    enabled: bool,
+    // (series, value) pairs already logged
+    seen: HashSet<(String, String)>,
+    // index per value per series, kept in `path`
+    indexes: BTreeMap<String, BTreeMap<String, u64>>,
+    path: Option<PathBuf>,
+}
+
+impl UnknownModes {
+    /// Log unknown modes, without giving them values.
+    pub fn log_only() -> UnknownModes {
+        UnknownModes { enabled: true, ..UnknownModes::default() }
+    }
+
+    /// Log unknown modes and give them the indexes kept in `path`, created if missing.
+    pub fn open(path: &Path) -> io::Result<UnknownModes> {
+        let indexes = match fs::read_to_string(path) {
+            Ok(contents) =>
+                serde_json
+                    ::from_str(&contents)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        Ok(UnknownModes {
+            enabled: true,
+            indexes,
+            path: Some(path.to_path_buf()),
+            ..UnknownModes::default()
+        })
+    }
+
+    /// Note that the series at `key` reported `value`, which no table converts, returning
+    /// its index if indexes are kept.
+    pub fn sighting(&mut self, key: &str, value: &str) -> Option<f64> {
+        if !self.enabled {
+            return None;
+        }
+        if self.seen.insert((key.to_string(), value.to_string())) {
+            warn!("NEW MODE: {} reported {:?}, which no mapping table knows", key, value);
+        }
+
+        let path = self.path.as_ref()?;
+        let series = self.indexes.entry(key.to_string()).or_default();
+        if let Some(index) = series.get(value) {
+            return Some(*index as f64);
+        }
+        let index = series.values().max().map_or(FIRST_INDEX, |index| index + 1);
+        series.insert(value.to_string(), index);
+        info!("assigned index {} to mode {:?} of {}", index, value, key);
+
+        // written to the side and renamed, so a crash can't leave a truncated file
+        let temp = path.with_extension("tmp");
+        let written = serde_json
+            ::to_string_pretty(&self.indexes)
+            .map_err(io::Error::from)
+            .and_then(|contents| fs::write(&temp, contents + "\n"))
+            .and_then(|_| fs::rename(&temp, path));
+        if let Err(e) = written {
+            error!("failed to save mode indexes to {}: {}", path.display(), e);
+        }
+        Some(index as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_or_log_only_gives_no_index() {
+        assert_eq!(UnknownModes::default().sighting("hvac/mode", "turbo"), None);
+        assert_eq!(UnknownModes::log_only().sighting("hvac/mode", "turbo"), None);
+    }
+
+    #[test]
+    fn indexes_are_stable_per_series_and_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("homie-modes-{}.json", std::process::id()));
+        let mut modes = UnknownModes::open(&path).unwrap();
+        assert_eq!(modes.sighting("hvac/mode", "turbo"), Some(100.0));
+        assert_eq!(modes.sighting("hvac/mode", "quiet"), Some(101.0));
+        assert_eq!(modes.sighting("hvac/mode", "turbo"), Some(100.0));
+        assert_eq!(modes.sighting("fan/mode", "quiet"), Some(100.0));
+
+        let mut reopened = UnknownModes::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.sighting("hvac/mode", "quiet"), Some(101.0));
+        assert_eq!(reopened.sighting("hvac/mode", "eco"), Some(102.0));
+    }
+
+    #[test]
+    fn corrupt_index_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!("homie-modes-corrupt-{}.json", std::process::id()));
+        std::fs::write(&path, "{ not json").unwrap();
+        let err = UnknownModes::open(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
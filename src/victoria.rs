@@ -0,0 +1,90 @@
+use crate::point::HomiePoint;
+
+/// Writes points to VictoriaMetrics' Influx-compatible `/write` endpoint. Unlike Influx v2,
+/// VictoriaMetrics needs no org, bucket or token.
+pub struct VictoriaMetricsClient {
+    http: reqwest::Client,
+    write_url: String,
+    float_precision: Option<usize>,
+}
+
+impl VictoriaMetricsClient {
+    pub fn new(base_url: &str, float_precision: Option<usize>) -> VictoriaMetricsClient {
+        VictoriaMetricsClient {
+            http: reqwest::Client::new(),
+            write_url: format!("{}/write?precision=s", base_url.trim_end_matches('/')),
+            float_precision,
+        }
+    }
+
+    pub fn write_url(&self) -> &str {
+        &self.write_url
+    }
+
+    pub async fn write(&self, points: &[HomiePoint]) -> Result<(), reqwest::Error> {
+        let body = points
+            .iter()
+            .map(|point| point.to_line_protocol(self.float_precision))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.http.post(&self.write_url).body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn writes_to_the_seconds_precision_endpoint() {
+        let client = VictoriaMetricsClient::new("http://vm:8428/", None);
+        assert_eq!(client.write_url(), "http://vm:8428/write?precision=s");
+    }
+
+    #[tokio::test]
+    async fn posts_points_as_line_protocol() {
+        // a one-request server handing back what it was sent
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut().write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+
+        let client = VictoriaMetricsClient::new(&format!("http://127.0.0.1:{}", port), Some(1));
+        let points = [
+            HomiePoint::new(21.54, "thermostat", "zone", "temperature", 1_700_000_000),
+            HomiePoint::new(40.0, "thermostat", "zone", "humidity", 1_700_000_000)
+        ];
+        client.write(&points).await.unwrap();
+
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /write?precision=s "), "{}", request_line);
+        let lines: Vec<String> = points.iter().map(|point| point.to_line_protocol(Some(1))).collect();
+        assert_eq!(body, lines.join("\n"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// The window after an MQTT reconnect in which the broker replays every retained value.
+/// Values arriving in it still update per-series state, they just aren't written.
+#[derive(Debug)]
+pub struct Warmup {
+    window: Duration,
+    until: Option<Instant>,
+}
+
+impl Warmup {
+    pub fn new(window: Duration) -> Warmup {
+        Warmup { window, until: None }
+    }
+
+    /// Start the window at `now`, returning whether there is one to start.
+    pub fn reconnected(&mut self, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return false;
+        }
+        self.until = Some(now + self.window);
+        true
+    }
+
+    pub fn is_warming_up(&self, now: Instant) -> bool {
+        self.until.is_some_and(|until| now < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::{ Config, SmoothingConfig };
+    use crate::control::Control;
+    use crate::pipeline::{ EmptyValue, Options, Pipeline };
+    use crate::point::FieldValue;
+    use crate::smoothing::Smoothing;
+
+    fn pipeline() -> Pipeline {
+        let smoothing = SmoothingConfig { method: Smoothing::Ema { alpha: 0.5 }, emit_raw: false };
+        let config = Config {
+            smoothing: HashMap::from([("temperature".to_string(), smoothing)]),
+            ..Config::default()
+        };
+        let options = Options {
+            trim: true,
+            dump_raw: false,
+            series_cache_size: 16,
+            empty_value: EmptyValue::Zero,
+            emit_provenance: false,
+            emit_raw_string: false,
+            enum_string_tag: false,
+        };
+        Pipeline::new(config, options, Arc::new(Control::default()))
+    }
+
+    #[test]
+    fn no_window_without_warmup() {
+        let mut warmup = Warmup::new(Duration::ZERO);
+        let now = Instant::now();
+        assert!(!warmup.reconnected(now));
+        assert!(!warmup.is_warming_up(now));
+    }
+
+    #[test]
+    fn window_ends_after_reconnect() {
+        let mut warmup = Warmup::new(Duration::from_secs(30));
+        let now = Instant::now();
+        assert!(!warmup.is_warming_up(now));
+        assert!(warmup.reconnected(now));
+        assert!(warmup.is_warming_up(now + Duration::from_secs(29)));
+        assert!(!warmup.is_warming_up(now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn values_during_warmup_update_state_but_are_not_forwarded() {
+        let mut pipeline = pipeline();
+        let mut warmup = Warmup::new(Duration::from_secs(30));
+        let now = Instant::now();
+        warmup.reconnected(now);
+
+        // what the main loop does: process every value, forward only outside the window
+        let mut forwarded = Vec::new();
+        for (value, at) in [("10", now), ("20", now + Duration::from_secs(31))] {
+            let points = pipeline.process("thermostat", "zone", "temperature", value.to_string(), 0);
+            assert_eq!(points.len(), 1);
+            if !warmup.is_warming_up(at) {
+                forwarded.extend(points);
+            }
+        }
+
+        // the held value seeded the average the forwarded one is smoothed into
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].fields[0].1, FieldValue::Float(15.0));
+    }
+}
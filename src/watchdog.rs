@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Notices a subscription that silently broke: connected, but no property values arriving.
+#[derive(Debug)]
+pub struct Watchdog {
+    // None disables it
+    timeout: Option<Duration>,
+    last_change: Instant,
+}
+
+impl Watchdog {
+    /// A watchdog firing after `timeout` without changes, starting from `now`; a zero
+    /// timeout never fires.
+    pub fn new(timeout: Duration, now: Instant) -> Watchdog {
+        Watchdog { timeout: (!timeout.is_zero()).then_some(timeout), last_change: now }
+    }
+
+    /// Note a poll that returned `changes` property values at `now`, returning whether it
+    /// has been `timeout` since the last one.
+    pub fn poll(&mut self, changes: usize, now: Instant) -> bool {
+        if changes > 0 {
+            self.last_change = now;
+            return false;
+        }
+        self.timeout.is_some_and(|timeout| now.duration_since(self.last_change) >= timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_after_timeout_without_changes() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(Duration::from_secs(60), start);
+        assert!(!watchdog.poll(0, start + Duration::from_secs(59)));
+        assert!(watchdog.poll(0, start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn changes_reset_it() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(Duration::from_secs(60), start);
+        assert!(!watchdog.poll(3, start + Duration::from_secs(50)));
+        assert!(!watchdog.poll(0, start + Duration::from_secs(100)));
+        assert!(watchdog.poll(0, start + Duration::from_secs(110)));
+    }
+
+    #[test]
+    fn zero_disables_it() {
+        let start = Instant::now();
+        let mut watchdog = Watchdog::new(Duration::ZERO, start);
+        assert!(!watchdog.poll(0, start + Duration::from_secs(86_400)));
+    }
+}